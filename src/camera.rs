@@ -35,7 +35,9 @@ fn move_camera(
     mut players: Query<(&mut Player, &Transform), With<LocalPlayer>>,
 ) {
     let (camera_entity, mut transform) = camera.single_mut();
-    let (mut player, player_transform) = players.single_mut();
+    let Ok((mut player, player_transform)) = players.get_single_mut() else {
+        return;
+    };
 
     let target_position = player_transform.translation + Vec3::new(5.0, 5.0, 5.0);
     transform.translation = transform.translation.lerp(
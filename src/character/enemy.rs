@@ -1,7 +1,11 @@
+pub mod ai;
+
 use bevy::{ecs::system::EntityCommands, prelude::*};
 use bevy_replicon::replicon_core::replication_rules::AppReplicationExt;
 use serde::{Deserialize, Serialize};
 
+use crate::network::protocol::ProtocolAppExt;
+
 use super::{
     player::{self, interaction_point::InteractionPoint},
     CharacterPhysicsBundle,
@@ -11,8 +15,11 @@ pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.replicate::<Enemy>()
+        app.add_plugins(ai::EnemyAiPlugin)
+            .replicate::<Enemy>()
+            .add_protocol_type::<Enemy>()
             .replicate::<DummyEnemy>()
+            .add_protocol_type::<DummyEnemy>()
             .add_systems(PostUpdate, init_enemies);
     }
 }
@@ -0,0 +1,259 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use bevy_replicon::replicon_core::replication_rules::AppReplicationExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{
+        health::{DamageEvent, DamageSource, Downed, Health},
+        player::{
+            input::{DAMPING, GRAVITY, SPEED},
+            Player,
+        },
+        CharacterVectors, MoveCharacters,
+    },
+    navigation::{NavMesh, NavigationPath},
+    network::{has_server, protocol::ProtocolAppExt},
+};
+
+use super::Enemy;
+
+/// Angle between consecutive patrol waypoints, spreads them evenly around the home.
+const GOLDEN_ANGLE: f32 = TAU * 0.381_966;
+/// Distance at which patrol waypoint counts as reached.
+const WAYPOINT_TOLERANCE: f32 = 0.3;
+
+pub struct EnemyAiPlugin;
+
+impl Plugin for EnemyAiPlugin {
+    fn build(&self, app: &mut App) {
+        app.replicate::<EnemyState>()
+            .add_protocol_type::<EnemyState>()
+            .init_resource::<EnemyAiSettings>()
+            .add_systems(FixedUpdate, think.run_if(has_server).before(MoveCharacters))
+            .add_systems(Update, tint_enemies);
+    }
+}
+
+/// Behavior of enemies driven by the server.
+#[derive(Debug, Clone, Resource)]
+pub struct EnemyAiSettings {
+    /// Distance at which enemies notice players.
+    pub perception_radius: f32,
+    /// Distance at which enemies stop chasing and attack.
+    pub attack_range: f32,
+    /// Damage dealt by a single attack.
+    pub attack_damage: f32,
+    /// Seconds between attacks.
+    pub attack_interval: f32,
+    /// Number of players in perception radius from which enemies flee.
+    pub flee_crowd: usize,
+    /// Part of health below which enemies flee from any player.
+    pub flee_health: f32,
+    /// Multiplier of player speed.
+    pub speed: f32,
+    /// Seconds of standing still between patrols.
+    pub idle_time: f32,
+    /// Seconds after which unfinished patrol is given up, e.g. when the waypoint is blocked.
+    pub patrol_time: f32,
+    /// Distance of patrol waypoints from the place where enemy spawned.
+    pub patrol_radius: f32,
+}
+
+impl Default for EnemyAiSettings {
+    fn default() -> Self {
+        Self {
+            perception_radius: 6.0,
+            attack_range: 1.2,
+            attack_damage: 10.0,
+            attack_interval: 1.0,
+            flee_crowd: 3,
+            flee_health: 0.25,
+            speed: 0.6,
+            idle_time: 2.0,
+            patrol_time: 8.0,
+            patrol_radius: 4.0,
+        }
+    }
+}
+
+/// What the enemy is doing, replicated so clients can show it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Component, Serialize, Deserialize)]
+pub enum EnemyState {
+    #[default]
+    Idle,
+    Patrol,
+    Chase,
+    Attack,
+    Flee,
+}
+
+impl EnemyState {
+    fn color(self) -> Color {
+        match self {
+            EnemyState::Idle => Color::RED,
+            EnemyState::Patrol => Color::rgb(0.8, 0.3, 0.3),
+            EnemyState::Chase => Color::ORANGE_RED,
+            EnemyState::Attack => Color::CRIMSON,
+            EnemyState::Flee => Color::YELLOW,
+        }
+    }
+}
+
+/// Memory of enemy driven by the server, not replicated.
+#[derive(Debug, Clone, Component)]
+pub struct EnemyBrain {
+    /// Place around which the enemy patrols.
+    home: Vec3,
+    /// Index of the current patrol waypoint.
+    waypoint: u32,
+    /// Seconds spent in the current state.
+    elapsed: f32,
+    /// Seconds until the next attack is ready.
+    attack_cooldown: f32,
+}
+
+#[derive(Bundle)]
+pub struct EnemyAiBundle {
+    state: EnemyState,
+    brain: EnemyBrain,
+    path: NavigationPath,
+}
+
+impl EnemyAiBundle {
+    pub fn new(home: Vec3) -> Self {
+        Self {
+            state: EnemyState::Idle,
+            brain: EnemyBrain {
+                home,
+                waypoint: 0,
+                elapsed: 0.0,
+                attack_cooldown: 0.0,
+            },
+            path: NavigationPath::default(),
+        }
+    }
+}
+
+impl EnemyBrain {
+    fn waypoint_position(&self, radius: f32) -> Vec3 {
+        let angle = self.waypoint as f32 * GOLDEN_ANGLE;
+        self.home + Vec3::new(angle.cos(), 0.0, angle.sin()) * radius
+    }
+}
+
+/// Chooses state of every enemy from players around it, and moves it accordingly.
+#[allow(clippy::type_complexity)]
+fn think(
+    mut damage_event: EventWriter<DamageEvent>,
+    mut enemies: Query<
+        (
+            Entity,
+            &Transform,
+            &mut CharacterVectors,
+            &mut EnemyState,
+            &mut EnemyBrain,
+            &mut NavigationPath,
+            Option<&Health>,
+        ),
+        With<Enemy>,
+    >,
+    players: Query<(Entity, &Transform), (With<Player>, Without<Downed>)>,
+    navmesh: Res<NavMesh>,
+    settings: Res<EnemyAiSettings>,
+    time: Res<Time>,
+) {
+    for (entity, transform, mut vectors, mut state, mut brain, mut path, health) in &mut enemies {
+        let position = transform.translation;
+        let mut nearest: Option<(f32, Entity, Vec3)> = None;
+        let mut crowd = Vec::new();
+        for (player, player_transform) in &players {
+            let distance = player_transform.translation.distance(position);
+            if distance > settings.perception_radius {
+                continue;
+            }
+            crowd.push(player_transform.translation);
+            if nearest.map_or(true, |(x, ..)| distance < x) {
+                nearest = Some((distance, player, player_transform.translation));
+            }
+        }
+
+        brain.elapsed += time.delta_seconds();
+        brain.attack_cooldown -= time.delta_seconds();
+        let wounded = health.map_or(false, |x| x.fraction() < settings.flee_health);
+        let (next, direction) = match nearest {
+            _ if !crowd.is_empty() && (wounded || crowd.len() >= settings.flee_crowd.max(1)) => {
+                let center = crowd.iter().sum::<Vec3>() / crowd.len() as f32;
+                (EnemyState::Flee, position - center)
+            }
+            Some((distance, player, _)) if distance <= settings.attack_range => {
+                if brain.attack_cooldown <= 0.0 {
+                    brain.attack_cooldown = settings.attack_interval;
+                    damage_event.send(DamageEvent {
+                        target: player,
+                        amount: settings.attack_damage,
+                        source: DamageSource::Entity(entity),
+                    });
+                }
+                (EnemyState::Attack, Vec3::ZERO)
+            }
+            Some((_, _, target)) => (
+                EnemyState::Chase,
+                path.direction(&navmesh, position, target),
+            ),
+            None => match *state {
+                EnemyState::Patrol => {
+                    let waypoint = brain.waypoint_position(settings.patrol_radius);
+                    let offset = (waypoint - position) * Vec3::new(1.0, 0.0, 1.0);
+                    if offset.length() < WAYPOINT_TOLERANCE || brain.elapsed > settings.patrol_time
+                    {
+                        brain.waypoint = brain.waypoint.wrapping_add(1);
+                        (EnemyState::Idle, Vec3::ZERO)
+                    } else {
+                        (
+                            EnemyState::Patrol,
+                            path.direction(&navmesh, position, waypoint),
+                        )
+                    }
+                }
+                EnemyState::Idle if brain.elapsed > settings.idle_time => {
+                    (EnemyState::Patrol, Vec3::ZERO)
+                }
+                _ => (EnemyState::Idle, Vec3::ZERO),
+            },
+        };
+
+        if *state != next {
+            brain.elapsed = 0.0;
+            path.clear();
+        }
+        state.set_if_neq(next);
+        steer(&mut vectors, direction, settings.speed);
+    }
+}
+
+/// Same movement as players use, walking horizontally towards the direction.
+fn steer(vectors: &mut CharacterVectors, direction: Vec3, speed: f32) {
+    let direction = Vec3::new(direction.x, 0.0, direction.z).normalize_or_zero();
+    vectors.velocity += Vec3::new(0.0, -GRAVITY, 0.0);
+    vectors.velocity += direction * SPEED * speed;
+
+    vectors.velocity.x *= DAMPING;
+    vectors.velocity.z *= DAMPING;
+}
+
+#[allow(clippy::type_complexity)]
+fn tint_enemies(
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<
+        (&EnemyState, &Handle<StandardMaterial>),
+        Or<(Changed<EnemyState>, Added<Handle<StandardMaterial>>)>,
+    >,
+) {
+    for (state, material) in &query {
+        if let Some(material) = materials.get_mut(material) {
+            material.base_color = state.color();
+        }
+    }
+}
@@ -0,0 +1,219 @@
+pub mod health_bar;
+
+use bevy::{ecs::query::Has, prelude::*};
+use bevy_egui::EguiPlugin;
+use bevy_replicon::{
+    network_event::server_event::ToClients, replicon_core::replication_rules::AppReplicationExt,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chat::ChatServerEvent,
+    network::{client::ClientId, has_server, protocol::ProtocolAppExt},
+};
+
+use super::{
+    enemy::Enemy,
+    player::{self, input::InputQueue, Player},
+    CharacterVectors, MoveCharacters,
+};
+
+/// Health of every character when spawned.
+pub const MAX_HEALTH: f32 = 100.0;
+/// Seconds after taking damage during which character takes no more damage.
+const INVULNERABILITY_TIME: f32 = 0.5;
+/// Seconds for which downed player waits before respawning.
+const RESPAWN_TIME: f32 = 5.0;
+/// Seconds after respawn during which player takes no damage.
+const RESPAWN_INVULNERABILITY_TIME: f32 = 2.0;
+
+pub struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.replicate::<Health>()
+            .add_protocol_type::<Health>()
+            .replicate::<Downed>()
+            .add_protocol_type::<Downed>()
+            .add_event::<DamageEvent>()
+            .add_event::<DeathEvent>()
+            .add_systems(
+                FixedUpdate,
+                (
+                    init_health,
+                    tick_invulnerability,
+                    apply_damage,
+                    handle_deaths,
+                    respawn_players,
+                )
+                    .chain()
+                    .after(MoveCharacters)
+                    .run_if(has_server),
+            );
+
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(health_bar::HealthBarPlugin);
+        }
+    }
+}
+
+/// Health of a character, changed only by the server.
+#[derive(Debug, Clone, Copy, Component, Serialize, Deserialize)]
+pub struct Health {
+    pub current: f32,
+    pub max: f32,
+}
+
+impl Health {
+    pub fn new(max: f32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        (self.current / self.max).clamp(0.0, 1.0)
+    }
+}
+
+/// Player which lost all of its health and waits for respawn.
+#[derive(Debug, Clone, Copy, Component, Serialize, Deserialize)]
+pub struct Downed;
+
+/// Seconds until downed player respawns, only on the server.
+#[derive(Component)]
+struct RespawnTimer(f32);
+
+/// Seconds during which character takes no damage, only on the server.
+#[derive(Component)]
+struct Invulnerable(f32);
+
+/// Who caused the damage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageSource {
+    Client(ClientId),
+    Entity(Entity),
+}
+
+/// Damage dealt to a character, applied by the server.
+#[derive(Debug, Clone, Event)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    pub source: DamageSource,
+}
+
+/// Character lost all of its health.
+#[derive(Debug, Clone, Event)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub source: DamageSource,
+}
+
+/// Gives health to characters spawned on the server, clients receive it by replication.
+fn init_health(
+    mut commands: Commands,
+    characters: Query<Entity, (With<CharacterVectors>, Without<Health>)>,
+) {
+    for entity in &characters {
+        commands.entity(entity).insert(Health::new(MAX_HEALTH));
+    }
+}
+
+fn tick_invulnerability(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Invulnerable)>,
+    time: Res<Time>,
+) {
+    for (entity, mut invulnerable) in &mut query {
+        invulnerable.0 -= time.delta_seconds();
+        if invulnerable.0 <= 0.0 {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    mut damage_event: EventReader<DamageEvent>,
+    mut death_event: EventWriter<DeathEvent>,
+    mut query: Query<(&mut Health, Has<Invulnerable>, Has<Downed>)>,
+) {
+    for event in damage_event.read() {
+        // Target may be already dead, or not spawned with health yet.
+        let Ok((mut health, invulnerable, downed)) = query.get_mut(event.target) else {
+            continue;
+        };
+        if invulnerable || downed || health.current <= 0.0 || event.amount <= 0.0 {
+            continue;
+        }
+
+        health.current = (health.current - event.amount).max(0.0);
+        match health.current > 0.0 {
+            true => {
+                commands
+                    .entity(event.target)
+                    .insert(Invulnerable(INVULNERABILITY_TIME));
+            }
+            false => death_event.send(DeathEvent {
+                entity: event.target,
+                source: event.source,
+            }),
+        }
+    }
+}
+
+/// Despawns dead enemies and downs dead players until they respawn.
+fn handle_deaths(
+    mut commands: Commands,
+    mut death_event: EventReader<DeathEvent>,
+    mut chat_event: EventWriter<ToClients<ChatServerEvent>>,
+    characters: Query<(Option<&Player>, Has<Enemy>)>,
+) {
+    for DeathEvent { entity, source } in death_event.read() {
+        let Ok((player, enemy)) = characters.get(*entity) else {
+            continue;
+        };
+
+        if let Some(player) = player {
+            info!("{player} is down, damaged by {source:?}.");
+            chat_event.send(ChatServerEvent::system(format!("{player} is down.")));
+            commands
+                .entity(*entity)
+                .insert((Downed, RespawnTimer(RESPAWN_TIME)));
+        } else if enemy {
+            debug!("Enemy {entity:?} killed by {source:?}.");
+            commands.entity(*entity).despawn_recursive();
+        }
+    }
+}
+
+fn respawn_players(
+    mut commands: Commands,
+    mut query: Query<(
+        Entity,
+        &mut RespawnTimer,
+        &mut Health,
+        &mut Transform,
+        &mut CharacterVectors,
+        Option<&mut InputQueue>,
+    )>,
+    time: Res<Time>,
+) {
+    for (entity, mut timer, mut health, mut transform, mut vectors, queue) in &mut query {
+        timer.0 -= time.delta_seconds();
+        if timer.0 > 0.0 {
+            continue;
+        }
+
+        health.current = health.max;
+        transform.translation = player::SPAWN_POINT;
+        vectors.velocity = Vec3::ZERO;
+        // Inputs and reports of remote players were made at the place where they were downed.
+        if let Some(mut queue) = queue {
+            queue.reset_movement();
+        }
+        commands
+            .entity(entity)
+            .remove::<(Downed, RespawnTimer)>()
+            .insert(Invulnerable(RESPAWN_INVULNERABILITY_TIME));
+    }
+}
@@ -0,0 +1,60 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Align2, Color32},
+    EguiContexts,
+};
+
+use super::{Downed, Health};
+
+/// Height above the center of the character at which its health bar is shown.
+const HEALTH_BAR_HEIGHT: f32 = 0.9;
+/// Width of health bars in points.
+const HEALTH_BAR_WIDTH: f32 = 48.0;
+
+pub struct HealthBarPlugin;
+
+impl Plugin for HealthBarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            draw_health_bars.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Draws health of characters above their meshes, below name tags of players.
+fn draw_health_bars(
+    mut ctx: EguiContexts,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    characters: Query<(Entity, &Health, &GlobalTransform, Option<&Downed>)>,
+) {
+    let Some((camera, camera_transform)) = cameras.iter().find(|(camera, _)| camera.is_active)
+    else {
+        return;
+    };
+
+    for (entity, health, transform, downed) in &characters {
+        let position = transform.translation() + Vec3::Y * HEALTH_BAR_HEIGHT;
+        let Some(viewport_position) = camera.world_to_viewport(camera_transform, position) else {
+            continue;
+        };
+
+        egui::Area::new(("health_bar", entity))
+            .fixed_pos(egui::pos2(viewport_position.x, viewport_position.y))
+            .pivot(Align2::CENTER_BOTTOM)
+            .interactable(false)
+            .order(egui::Order::Background)
+            .show(ctx.ctx_mut(), |ui| match downed {
+                Some(_) => {
+                    ui.colored_label(Color32::LIGHT_RED, "Downed");
+                }
+                None => {
+                    ui.add(
+                        egui::ProgressBar::new(health.fraction())
+                            .desired_width(HEALTH_BAR_WIDTH)
+                            .fill(Color32::from_rgb(200, 40, 40)),
+                    );
+                }
+            });
+    }
+}
@@ -1,4 +1,5 @@
 pub mod enemy;
+pub mod health;
 pub mod player;
 
 use bevy::prelude::*;
@@ -6,7 +7,9 @@ use bevy_rapier3d::prelude::*;
 use bevy_replicon::replicon_core::replication_rules::{AppReplicationExt, Replication};
 use serde::{Deserialize, Serialize};
 
-use self::{enemy::EnemyPlugin, player::PlayerPlugin};
+use crate::network::protocol::ProtocolAppExt;
+
+use self::{enemy::EnemyPlugin, health::HealthPlugin, player::PlayerPlugin};
 
 pub struct CharacterPlugin;
 
@@ -15,8 +18,9 @@ pub struct MoveCharacters;
 
 impl Plugin for CharacterPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((PlayerPlugin, EnemyPlugin))
+        app.add_plugins((PlayerPlugin, EnemyPlugin, HealthPlugin))
             .replicate::<CharacterVectors>()
+            .add_protocol_type::<CharacterVectors>()
             .configure_sets(FixedUpdate, MoveCharacters.before(PhysicsSet::SyncBackend))
             .add_systems(
                 FixedUpdate,
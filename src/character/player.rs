@@ -1,70 +1,84 @@
+pub mod ballista;
+pub mod input;
 pub mod interaction_point;
+pub mod name_tag;
+pub mod registry;
+pub mod validation;
 
 use std::fmt;
 
-use bevy::{ecs::system::EntityCommands, math::vec3, prelude::*};
-use bevy_egui::egui::WidgetText;
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy_egui::{egui::WidgetText, EguiPlugin};
 use bevy_replicon::{
     client::ClientSet,
-    network_event::{
-        client_event::{ClientEventAppExt, FromClient},
-        server_event::{SendMode, ServerEventAppExt, ToClients},
-        EventType,
-    },
     replicon_core::{
         dont_replicate::CommandDontReplicateExt,
         replication_rules::{AppReplicationExt, Replication},
     },
-    server::ServerSet,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::network::{
     client::{Client, ClientId},
-    has_client, has_client_and_local_player, has_local_player, has_server,
-    replication::transform::SyncedTransform,
+    has_client,
+    protocol::ProtocolAppExt,
+    replication::transform::{Predicted, SnapshotBuffer},
 };
 
-use super::{CharacterPhysicsBundle, CharacterVectors, MoveCharacters};
+use self::input::{InputHistory, InputQueue};
+
+use super::{CharacterPhysicsBundle, CharacterVectors};
 
 pub const RADIUS: f32 = 0.4;
 pub const HALF_HEIGHT: f32 = 0.4;
+/// Position where players spawn and respawn.
+pub const SPAWN_POINT: Vec3 = Vec3::new(0.0, 3.0, 0.0);
+
+/// Colors which players choose from in the lobby.
+pub const PLAYER_COLORS: [Color; 8] = [
+    Color::WHITE,
+    Color::rgb(0.9, 0.3, 0.3),
+    Color::rgb(0.3, 0.5, 0.9),
+    Color::rgb(0.3, 0.8, 0.4),
+    Color::rgb(0.9, 0.8, 0.3),
+    Color::rgb(0.7, 0.4, 0.9),
+    Color::rgb(0.9, 0.6, 0.3),
+    Color::rgb(0.3, 0.8, 0.8),
+];
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(interaction_point::InteractionPointPlugin)
-            .add_server_event::<TransformServerEvent>(EventType::Ordered)
-            .add_client_event::<TransformClientEvent>(EventType::Ordered)
-            .replicate::<Player>()
-            .add_systems(
-                PreUpdate,
-                (
-                    init_players.after(ClientSet::Receive).run_if(has_client),
-                    transform_server_handler
-                        .after(ServerSet::Receive)
-                        .run_if(has_server),
-                    transform_client_handler
-                        .after(ClientSet::Receive)
-                        .run_if(has_client),
-                ),
-            )
-            .add_systems(
-                FixedUpdate,
-                (
-                    control.run_if(has_local_player).before(MoveCharacters),
-                    transform_server_sender.run_if(has_server),
-                    transform_client_sender.run_if(has_client_and_local_player),
-                ),
-            );
+        app.add_plugins((
+            ballista::BallistaPlugin,
+            interaction_point::InteractionPointPlugin,
+            input::InputPlugin,
+            registry::RegistryPlugin,
+            validation::ValidationPlugin,
+        ))
+        .replicate::<Player>()
+        .add_protocol_type::<Player>()
+        .add_systems(
+            PreUpdate,
+            init_players.after(ClientSet::Receive).run_if(has_client),
+        )
+        .add_systems(Update, update_colors);
+
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.add_plugins(name_tag::NameTagPlugin);
+        }
     }
 }
 
 #[derive(Copy, Clone)]
 pub enum PlayerKind {
+    /// Player controlled on this machine.
     Local,
+    /// Player controlled on other machine, which only follows transforms sent by the server.
     Remote,
+    /// Player controlled on other machine, which is simulated by the server from its inputs.
+    Simulated,
 }
 
 pub fn spawn(
@@ -73,40 +87,50 @@ pub fn spawn(
     materials: &mut ResMut<Assets<StandardMaterial>>,
     player: Player,
     kind: PlayerKind,
-) {
-    let transform = Transform::from_xyz(0.0, 3.0, 0.0);
+) -> Entity {
+    let transform = Transform::from_translation(SPAWN_POINT);
+    let color = player.color();
     let mut entity_commands = commands.spawn((
         player,
-        SharedPlayerBundle::new(meshes, materials, transform, kind),
+        SharedPlayerBundle::new(meshes, materials, transform, color),
     ));
-    let entity_commands = entity_commands.dont_replicate::<Transform>();
+    let entity_commands = entity_commands.dont_replicate::<CharacterVectors>();
 
     add_kind_dependent_components_to_players(entity_commands, kind, transform);
+    entity_commands.id()
 }
 
 fn init_players(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    spawned_players: Query<(Entity, &Player), Added<Player>>,
+    spawned_players: Query<(Entity, &Player, Option<&Transform>), Added<Player>>,
     client: Res<Client>,
 ) {
-    for (entity, player) in &spawned_players {
+    for (entity, player, transform) in &spawned_players {
         let kind = match player.client_id == client.id {
             true => PlayerKind::Local,
             false => PlayerKind::Remote,
         };
 
-        let transform = Transform::from_xyz(0.0, 3.0, 0.0);
+        // Transform is replicated together with the player.
+        let transform = transform
+            .copied()
+            .unwrap_or_else(|| Transform::from_translation(SPAWN_POINT));
         let mut entity_commands = commands.entity(entity);
         entity_commands.insert(SharedPlayerBundle::new(
             &mut meshes,
             &mut materials,
             transform,
-            kind,
+            player.color(),
         ));
 
         add_kind_dependent_components_to_players(&mut entity_commands, kind, transform);
+
+        // Local player of the client predicts its movement until the server confirms it.
+        if let PlayerKind::Local = kind {
+            entity_commands.insert(InputHistory::default());
+        }
     }
 }
 
@@ -117,17 +141,26 @@ fn add_kind_dependent_components_to_players(
 ) {
     match kind {
         PlayerKind::Local => {
-            entity_commands.insert(LocalPlayerBundle {
-                local_player: LocalPlayer,
-                character_physics: CharacterPhysicsBundle::new(HALF_HEIGHT, RADIUS),
-            });
+            entity_commands
+                .insert(LocalPlayerBundle {
+                    local_player: LocalPlayer,
+                    predicted: Predicted,
+                    character_physics: CharacterPhysicsBundle::new(HALF_HEIGHT, RADIUS),
+                })
+                .remove::<SnapshotBuffer>();
             entity_commands
                 .commands()
                 .insert_resource(LocalPlayerResource);
         }
         PlayerKind::Remote => {
             entity_commands.insert(RemotePlayerBundle {
-                synced_transform: transform.into(),
+                snapshots: SnapshotBuffer::default(),
+            });
+        }
+        PlayerKind::Simulated => {
+            entity_commands.insert(SimulatedPlayerBundle {
+                input_queue: InputQueue::default(),
+                character_physics: CharacterPhysicsBundle::new(HALF_HEIGHT, RADIUS),
             });
         }
     };
@@ -137,11 +170,36 @@ fn add_kind_dependent_components_to_players(
 pub struct Player {
     pub client_id: ClientId,
     pub attached_camera: Option<Entity>,
+    /// Display name chosen in the lobby, empty until the player chooses one.
+    pub name: String,
+    /// Index into [`PLAYER_COLORS`].
+    pub color: u8,
+    /// Whether the player is ready to start the match.
+    pub ready: bool,
+}
+
+impl Player {
+    pub fn new(client_id: ClientId, color: u8) -> Self {
+        Self {
+            client_id,
+            attached_camera: None,
+            name: String::new(),
+            color,
+            ready: false,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        PLAYER_COLORS[self.color as usize % PLAYER_COLORS.len()]
+    }
 }
 
 impl fmt::Display for Player {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Player {}", self.client_id)
+        match self.name.is_empty() {
+            true => write!(f, "Player {}", self.client_id),
+            false => write!(f, "{}", self.name),
+        }
     }
 }
 
@@ -160,6 +218,8 @@ pub struct LocalPlayerResource;
 #[derive(Bundle)]
 struct LocalPlayerBundle {
     local_player: LocalPlayer,
+    /// Position of local player is predicted from inputs and reconciled by the server.
+    predicted: Predicted,
     character_physics: CharacterPhysicsBundle,
 }
 
@@ -174,7 +234,13 @@ struct SharedPlayerBundle {
 
 #[derive(Bundle)]
 struct RemotePlayerBundle {
-    synced_transform: SyncedTransform,
+    snapshots: SnapshotBuffer,
+}
+
+#[derive(Bundle)]
+struct SimulatedPlayerBundle {
+    input_queue: InputQueue,
+    character_physics: CharacterPhysicsBundle,
 }
 
 impl SharedPlayerBundle {
@@ -182,7 +248,7 @@ impl SharedPlayerBundle {
         meshes: &mut ResMut<Assets<Mesh>>,
         materials: &mut ResMut<Assets<StandardMaterial>>,
         transform: Transform,
-        kind: PlayerKind,
+        color: Color,
     ) -> SharedPlayerBundle {
         Self {
             transform: TransformBundle::from_transform(transform),
@@ -195,105 +261,23 @@ impl SharedPlayerBundle {
                 }
                 .into(),
             ),
-            material: materials.add(
-                match kind {
-                    PlayerKind::Local => Color::WHITE,
-                    PlayerKind::Remote => Color::GRAY,
-                }
-                .into(),
-            ),
+            material: materials.add(color.into()),
             visibility: VisibilityBundle::default(),
             replication: Replication,
         }
     }
 }
 
-fn control(mut query: Query<&mut CharacterVectors, With<LocalPlayer>>, input: Res<Input<KeyCode>>) {
-    let mut vectors = query.single_mut();
-    vectors.velocity += vec3(0.0, -0.005, 0.0);
-
-    let mut movement = Vec3::ZERO;
-    if input.pressed(KeyCode::A) {
-        movement += vec3(-1.0, 0.0, 1.0);
-    }
-    if input.pressed(KeyCode::S) {
-        movement += vec3(1.0, 0.0, 1.0);
-    }
-    if input.pressed(KeyCode::D) {
-        movement += vec3(1.0, 0.0, -1.0);
-    }
-    if input.pressed(KeyCode::W) {
-        movement += vec3(-1.0, 0.0, -1.0);
-    }
-    let speed = 0.015;
-    movement = movement.normalize_or_zero() * speed;
-    vectors.velocity += movement;
-
-    let damping = 0.8;
-    vectors.velocity.x *= damping;
-    vectors.velocity.z *= damping;
-}
-
-#[derive(Deserialize, Event, Serialize)]
-struct TransformServerEvent {
-    client_id: ClientId,
-    transform: SyncedTransform,
-}
-
-#[derive(Deserialize, Event, Serialize)]
-struct TransformClientEvent {
-    transform: SyncedTransform,
-}
-
-fn transform_server_sender(
-    mut event: EventWriter<ToClients<TransformServerEvent>>,
-    query: Query<(&Transform, &Player)>,
-) {
-    for (transform, player) in &mut query.iter() {
-        event.send(ToClients {
-            mode: SendMode::Broadcast,
-            event: TransformServerEvent {
-                client_id: player.client_id,
-                transform: (*transform).into(),
-            },
-        });
-    }
-}
-
-fn transform_client_handler(
-    mut event: EventReader<TransformServerEvent>,
-    mut query: Query<(&Player, &mut SyncedTransform), Without<LocalPlayer>>,
+/// Follows colors chosen by players in the lobby.
+fn update_colors(
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    query: Query<(&Player, &Handle<StandardMaterial>), Changed<Player>>,
 ) {
-    for event in event.read() {
-        // Ignore LocalPlayer.
-        if let Some((_, mut transform)) =
-            query.iter_mut().find(|x| x.0.client_id == event.client_id)
-        {
-            *transform = event.transform.clone();
+    for (player, material) in &query {
+        if let Some(material) = materials.get_mut(material) {
+            if material.base_color != player.color() {
+                material.base_color = player.color();
+            }
         }
     }
 }
-
-fn transform_client_sender(
-    mut event: EventWriter<TransformClientEvent>,
-    query: Query<&Transform, With<LocalPlayer>>,
-) {
-    let transform = query.single();
-    event.send(TransformClientEvent {
-        transform: (*transform).into(),
-    });
-}
-
-fn transform_server_handler(
-    mut event: EventReader<FromClient<TransformClientEvent>>,
-    mut query: Query<(&Player, &mut SyncedTransform)>,
-) {
-    for FromClient { client_id, event } in event.read() {
-        let (_, mut transform) = query
-            .iter_mut()
-            .find(|x| x.0.client_id == client_id)
-            .expect("Expecting player to exist");
-
-        *transform = event.transform.clone();
-    }
-}
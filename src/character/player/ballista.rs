@@ -0,0 +1,279 @@
+use bevy::{ecs::query::Has, prelude::*, utils::HashMap, window::PrimaryWindow};
+use bevy_egui::{EguiContexts, EguiPlugin};
+use bevy_replicon::{
+    network_event::{
+        client_event::{ClientEventAppExt, FromClient},
+        EventType,
+    },
+    replicon_core::{replication_rules::AppReplicationExt, RepliconTick},
+    server::ServerSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::health::Downed,
+    chat::ChatUiState,
+    lobby::MatchState,
+    network::{
+        client::ClientId,
+        has_local_player, has_server,
+        protocol::ProtocolAppExt,
+        replication::transform::{InterpolationClock, InterpolationSettings},
+    },
+    projectile::{self, Projectile, ProjectileKind},
+};
+
+use super::{
+    interaction_point,
+    registry::{PlayerRegistry, UpdatePlayerRegistry},
+    LocalPlayer, Player, RADIUS,
+};
+
+/// Seconds of holding the button until the ballista is fully charged.
+const CHARGE_TIME: f32 = 1.2;
+/// Seconds between shots of a single player.
+const RELOAD_TIME: f32 = 0.8;
+/// Angle in radians above the horizon at which bolts are fired.
+const LAUNCH_ANGLE: f32 = 0.1;
+const MIN_SPEED: f32 = 8.0;
+const MAX_SPEED: f32 = 24.0;
+const MIN_DAMAGE: f32 = 10.0;
+const MAX_DAMAGE: f32 = 50.0;
+const BOLT_RADIUS: f32 = 0.1;
+const BOLT_GRAVITY: f32 = 9.81;
+const BOLT_DRAG: f32 = 0.05;
+/// Range of the shot of fully charged ballista, which hits instantly.
+const PIERCING_RANGE: f32 = 30.0;
+
+pub struct BallistaPlugin;
+
+impl Plugin for BallistaPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_client_event::<FireClientEvent>(EventType::Ordered)
+            .replicate::<Bolt>()
+            .add_protocol_type::<Bolt>()
+            .init_resource::<BallistaReloads>()
+            .add_systems(
+                PreUpdate,
+                (
+                    fire_server_handler.after(UpdatePlayerRegistry),
+                    forget_reloads,
+                )
+                    .after(ServerSet::Receive)
+                    .run_if(has_server),
+            )
+            .add_systems(PostUpdate, init_bolts);
+
+        // Headless server does not aim.
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.init_resource::<BallistaCharge>()
+                .add_systems(Update, aim.run_if(has_local_player));
+        }
+    }
+}
+
+/// Ballistic projectile fired from a ballista. It is not a Rapier body, it flies along the
+/// closed form of its projectile, so the server and clients agree on its flight.
+#[derive(Debug, Clone, Copy, Component, Serialize, Deserialize)]
+pub struct Bolt;
+
+/// Request of the client to fire its ballista.
+#[derive(Debug, Clone, Event, Serialize, Deserialize)]
+struct FireClientEvent {
+    direction: Vec3,
+    /// How long the ballista was charged, from 0 to 1.
+    charge: f32,
+    /// Tick at which the client rendered other characters, `None` when playing on the server.
+    view_tick: Option<f64>,
+}
+
+/// Time at which the local player started charging, `None` while not charging.
+#[derive(Debug, Default, Resource)]
+struct BallistaCharge {
+    started: Option<f32>,
+}
+
+/// Time of the last shot of every player by its entity, which is kept while the client
+/// reconnects, on the server.
+#[derive(Debug, Default, Resource)]
+struct BallistaReloads {
+    last_shot: HashMap<Entity, f32>,
+}
+
+/// Charges the ballista while the button is held and fires towards the cursor on release.
+#[allow(clippy::too_many_arguments)]
+fn aim(
+    mut ctx: EguiContexts,
+    mut fire_event: EventWriter<FireClientEvent>,
+    mut charge: ResMut<BallistaCharge>,
+    mut gizmos: Gizmos,
+    players: Query<(&Player, &Transform, Has<Downed>), With<LocalPlayer>>,
+    cameras: Query<(&GlobalTransform, &Camera)>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mouse: Res<Input<MouseButton>>,
+    match_state: Res<State<MatchState>>,
+    chat: Option<Res<ChatUiState>>,
+    clock: Res<InterpolationClock>,
+    settings: Res<InterpolationSettings>,
+    time: Res<Time>,
+) {
+    let Ok((player, transform, downed)) = players.get_single() else {
+        return;
+    };
+    let typing = chat.map_or(false, |x| x.typing);
+    let allowed = *match_state.get() == MatchState::Playing && !typing && !downed;
+    let Some(target) = interaction_point::get_interest_point(player, transform, &cameras, &window)
+        .filter(|_| allowed)
+    else {
+        charge.started = None;
+        return;
+    };
+
+    let now = time.elapsed_seconds();
+    if mouse.just_pressed(MouseButton::Left) && !ctx.ctx_mut().wants_pointer_input() {
+        charge.started = Some(now);
+    }
+    let Some(started) = charge.started else {
+        return;
+    };
+
+    let fraction = ((now - started) / CHARGE_TIME).min(1.0);
+    let direction = (target - transform.translation) * Vec3::new(1.0, 0.0, 1.0);
+    gizmos.line(
+        transform.translation,
+        transform.translation + direction.normalize_or_zero() * (1.0 + fraction * 2.0),
+        Color::WHITE * (1.0 - fraction) + Color::RED * fraction,
+    );
+
+    if mouse.just_released(MouseButton::Left) {
+        charge.started = None;
+        fire_event.send(FireClientEvent {
+            direction,
+            charge: fraction,
+            view_tick: clock.render_tick(&settings),
+        });
+    }
+}
+
+/// Removes reload times of despawned players.
+fn forget_reloads(
+    mut removed_players: RemovedComponents<Player>,
+    mut reloads: ResMut<BallistaReloads>,
+) {
+    for entity in removed_players.read() {
+        reloads.last_shot.remove(&entity);
+    }
+}
+
+/// Spawns projectiles of players which are allowed to fire, fully charged ballista pierces
+/// instantly.
+#[allow(clippy::too_many_arguments)]
+fn fire_server_handler(
+    mut commands: Commands,
+    mut fire_event: EventReader<FromClient<FireClientEvent>>,
+    mut reloads: ResMut<BallistaReloads>,
+    players: Query<(Entity, &Transform), (With<Player>, Without<Downed>)>,
+    registry: Res<PlayerRegistry>,
+    match_state: Res<State<MatchState>>,
+    tick: Res<RepliconTick>,
+    time: Res<Time>,
+) {
+    for FromClient { client_id, event } in fire_event.read() {
+        let client_id = ClientId::from(*client_id);
+        let Some((entity, transform)) = registry
+            .entity(client_id)
+            .and_then(|entity| players.get(entity).ok())
+        else {
+            continue;
+        };
+        let direction = Vec3::new(event.direction.x, 0.0, event.direction.z).normalize_or_zero();
+        if *match_state.get() != MatchState::Playing
+            || direction == Vec3::ZERO
+            || !event.charge.is_finite()
+        {
+            continue;
+        }
+
+        let now = time.elapsed_seconds();
+        if let Some(last_shot) = reloads.last_shot.get(&entity) {
+            if now - last_shot < RELOAD_TIME {
+                continue;
+            }
+        }
+        reloads.last_shot.insert(entity, now);
+
+        let charge = event.charge.clamp(0.0, 1.0);
+        let damage = MIN_DAMAGE + (MAX_DAMAGE - MIN_DAMAGE) * charge;
+        // Starts outside of the shooter, so it does not hit itself.
+        let origin = transform.translation + direction * (RADIUS + BOLT_RADIUS * 2.0);
+        let view_tick = event.view_tick.filter(|x| x.is_finite());
+
+        if charge >= 1.0 {
+            projectile::spawn(
+                &mut commands,
+                Projectile {
+                    shooter: client_id,
+                    kind: ProjectileKind::Hitscan {
+                        length: PIERCING_RANGE,
+                    },
+                    origin,
+                    velocity: direction,
+                    radius: BOLT_RADIUS,
+                    damage,
+                    fired_at: *tick,
+                },
+                view_tick,
+            );
+            continue;
+        }
+
+        let axis = direction.cross(Vec3::Y);
+        let velocity = Quat::from_axis_angle(axis, LAUNCH_ANGLE)
+            * direction
+            * (MIN_SPEED + (MAX_SPEED - MIN_SPEED) * charge);
+        projectile::spawn(
+            &mut commands,
+            Projectile {
+                shooter: client_id,
+                kind: ProjectileKind::Ballistic {
+                    gravity: BOLT_GRAVITY,
+                    drag: BOLT_DRAG,
+                },
+                origin,
+                velocity,
+                radius: BOLT_RADIUS,
+                damage,
+                fired_at: *tick,
+            },
+            view_tick,
+        )
+        .insert(Bolt);
+    }
+}
+
+/// Adds meshes to bolts spawned on this machine or received from the server.
+fn init_bolts(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    bolts: Query<Entity, Added<Bolt>>,
+) {
+    for entity in &bolts {
+        commands.entity(entity).insert((
+            meshes.add(
+                shape::Capsule {
+                    radius: BOLT_RADIUS,
+                    depth: BOLT_RADIUS * 4.0,
+                    ..default()
+                }
+                .into(),
+            ),
+            materials.add(StandardMaterial {
+                base_color: Color::rgba(0.7, 0.9, 1.0, 0.6),
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            }),
+            VisibilityBundle::default(),
+        ));
+    }
+}
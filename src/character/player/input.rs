@@ -0,0 +1,607 @@
+use std::collections::VecDeque;
+
+use bevy::{ecs::query::Has, math::vec3, prelude::*};
+use bevy_egui::EguiContexts;
+use bevy_rapier3d::prelude::*;
+use bevy_replicon::{
+    client::ClientSet,
+    network_event::{
+        client_event::{ClientEventAppExt, FromClient},
+        server_event::{SendMode, ServerEventAppExt, ToClients},
+        EventType,
+    },
+    server::ServerSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{health::Downed, CharacterVectors, MoveCharacters},
+    chat::ChatUiState,
+    lobby::MatchState,
+    network::{
+        client::ClientId, has_client_and_local_player, has_local_player, has_server,
+        replication::transform::SyncedTransform,
+    },
+};
+
+use super::{
+    registry::{PlayerRegistry, UpdatePlayerRegistry},
+    validation::{self, MovementValidationSettings, MovementViolationEvent},
+    LocalPlayer, Player,
+};
+
+pub const GRAVITY: f32 = 0.005;
+pub const SPEED: f32 = 0.015;
+pub const DAMPING: f32 = 0.8;
+
+/// Maximum number of inputs waiting for simulation on the server, the oldest ones are dropped.
+/// Inputs sent during a hitch of the client arrive at once, so overflow is not a violation.
+const MAX_QUEUED_INPUTS: usize = 8;
+/// Maximum number of inputs which are not acknowledged by the server yet.
+const MAX_PENDING_INPUTS: usize = 128;
+/// Distance between predicted and reconciled position above which local player is corrected.
+const RECONCILIATION_TOLERANCE: f32 = 0.01;
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_client_event::<InputClientEvent>(EventType::Ordered)
+            .add_server_event::<PlayerStateServerEvent>(EventType::Unreliable)
+            .add_systems(
+                PreUpdate,
+                (
+                    input_server_handler
+                        .after(ServerSet::Receive)
+                        .after(UpdatePlayerRegistry)
+                        .run_if(has_server),
+                    reconcile
+                        .after(ClientSet::Receive)
+                        .run_if(has_client_and_local_player),
+                ),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    control.run_if(has_local_player).before(MoveCharacters),
+                    simulate_remote.run_if(has_server).before(MoveCharacters),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                (validate_simulated_moves, player_state_sender)
+                    .chain()
+                    .after(PhysicsSet::Writeback)
+                    .before(ServerSet::Send)
+                    .run_if(has_server),
+            );
+    }
+}
+
+/// Movement requested by player in a single fixed tick.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PlayerInput {
+    pub movement: Vec3,
+}
+
+impl PlayerInput {
+    pub fn from_keyboard(input: &Input<KeyCode>) -> Self {
+        let mut movement = Vec3::ZERO;
+        if input.pressed(KeyCode::A) {
+            movement += vec3(-1.0, 0.0, 1.0);
+        }
+        if input.pressed(KeyCode::S) {
+            movement += vec3(1.0, 0.0, 1.0);
+        }
+        if input.pressed(KeyCode::D) {
+            movement += vec3(1.0, 0.0, -1.0);
+        }
+        if input.pressed(KeyCode::W) {
+            movement += vec3(-1.0, 0.0, -1.0);
+        }
+
+        Self {
+            movement: movement.normalize_or_zero(),
+        }
+    }
+
+    /// Applies input to character vectors, same on the server and on the predicting client.
+    pub fn apply(&self, vectors: &mut CharacterVectors) {
+        vectors.velocity += vec3(0.0, -GRAVITY, 0.0);
+        vectors.velocity += self.movement.normalize_or_zero() * SPEED;
+
+        vectors.velocity.x *= DAMPING;
+        vectors.velocity.z *= DAMPING;
+    }
+}
+
+/// Inputs of local player on the client, which are not yet acknowledged by the server.
+#[derive(Component, Default)]
+pub struct InputHistory {
+    next_sequence: u32,
+    pending: VecDeque<(u32, PlayerInput)>,
+    /// Sequence of the newest state of the server which was applied.
+    acknowledged: Option<u32>,
+}
+
+impl InputHistory {
+    fn push(&mut self, input: PlayerInput) -> u32 {
+        let sequence = self.next_sequence;
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+
+        if self.pending.len() >= MAX_PENDING_INPUTS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back((sequence, input));
+        sequence
+    }
+
+    /// Drops inputs simulated or rejected by the server and returns the rest, which are replayed
+    /// on top of the state of the server. States arrive unreliably, so older states than the
+    /// applied one return `None`.
+    fn acknowledge(
+        &mut self,
+        sequence: u32,
+        rejected: Option<u32>,
+    ) -> Option<impl Iterator<Item = &PlayerInput> + '_> {
+        if self.acknowledged.map_or(false, |x| !is_newer(sequence, x)) {
+            return None;
+        }
+        self.acknowledged = Some(sequence);
+
+        // Rejected inputs will never be simulated by the server, so they cannot be replayed.
+        self.pending.retain(|(x, _)| {
+            is_newer(*x, sequence) && rejected.map_or(true, |rejected| is_newer(*x, rejected))
+        });
+        Some(self.pending.iter().map(|(_, input)| input))
+    }
+}
+
+/// Inputs of remote player on the server, which are waiting for simulation.
+#[derive(Component, Default)]
+pub struct InputQueue {
+    inputs: VecDeque<(u32, PlayerInput)>,
+    /// Sequence of the last received input, including rejected ones.
+    last_received: Option<u32>,
+    last_sequence: Option<u32>,
+    /// Sequence of the last simulated input whose state was sent to the client.
+    sent_sequence: Option<u32>,
+    /// Position of the player after the last simulated move which passed validation.
+    baseline: Option<Vec3>,
+    /// Number of fixed ticks simulated since the baseline.
+    simulated_ticks: u32,
+    /// Sequence of the last rejected input, sent with every state, so the client does not
+    /// replay it even when some states are lost.
+    rejected: Option<u32>,
+}
+
+impl InputQueue {
+    /// Marks the input as received, returns `false` for duplicate and older inputs, which are
+    /// dropped.
+    fn receive(&mut self, sequence: u32) -> bool {
+        if self.last_received.map_or(false, |x| !is_newer(sequence, x)) {
+            return false;
+        }
+        self.last_received = Some(sequence);
+        true
+    }
+
+    fn push(&mut self, sequence: u32, input: PlayerInput) {
+        if self.inputs.len() >= MAX_QUEUED_INPUTS {
+            self.inputs.pop_front();
+        }
+        self.inputs.push_back((sequence, input));
+    }
+
+    /// Forgets queued inputs and the validation baseline after the server moved the player,
+    /// they belong to the old position.
+    pub fn reset_movement(&mut self) {
+        self.inputs.clear();
+        self.baseline = None;
+    }
+
+    /// Drops queued inputs and forces the client to return to the state of the server.
+    fn reject(&mut self, sequence: u32) {
+        self.inputs.clear();
+        self.rejected = Some(sequence);
+    }
+}
+
+/// Whether the sequence number follows the other one, handles wrapping around.
+fn is_newer(sequence: u32, other: u32) -> bool {
+    sequence.wrapping_sub(other) as i32 > 0
+}
+
+#[derive(Deserialize, Event, Serialize)]
+struct InputClientEvent {
+    sequence: u32,
+    input: PlayerInput,
+}
+
+#[derive(Deserialize, Event, Serialize)]
+struct PlayerStateServerEvent {
+    sequence: u32,
+    transform: SyncedTransform,
+    velocity: Vec3,
+    grounded: bool,
+    /// Sequence of the last input rejected by the server, which must not be replayed.
+    rejected: Option<u32>,
+}
+
+#[allow(clippy::type_complexity)]
+fn control(
+    mut ctx: EguiContexts,
+    mut query: Query<
+        (
+            &mut CharacterVectors,
+            Option<&mut InputHistory>,
+            Has<Downed>,
+        ),
+        With<LocalPlayer>,
+    >,
+    mut event: EventWriter<InputClientEvent>,
+    input: Res<Input<KeyCode>>,
+    match_state: Res<State<MatchState>>,
+    chat: Option<Res<ChatUiState>>,
+) {
+    let Ok((mut vectors, history, downed)) = query.get_single_mut() else {
+        return;
+    };
+    // Players wait in the lobby until the match starts, and stand still while typing or downed.
+    // Typing covers the chat and any other window, such as the console.
+    let typing = chat.map_or(false, |x| x.typing) || ctx.ctx_mut().wants_keyboard_input();
+    let player_input = match match_state.get() {
+        MatchState::Playing if !typing && !downed => PlayerInput::from_keyboard(&input),
+        _ => PlayerInput::default(),
+    };
+    player_input.apply(&mut vectors);
+
+    // Only clients keep history, host is authoritative over its own player.
+    if let Some(mut history) = history {
+        event.send(InputClientEvent {
+            sequence: history.push(player_input),
+            input: player_input,
+        });
+    }
+}
+
+fn input_server_handler(
+    mut event: EventReader<FromClient<InputClientEvent>>,
+    mut violations: EventWriter<MovementViolationEvent>,
+    mut query: Query<&mut InputQueue>,
+    registry: Res<PlayerRegistry>,
+    match_state: Res<State<MatchState>>,
+) {
+    for FromClient { client_id, event } in event.read() {
+        // Inputs may arrive before the player is spawned or after it is removed.
+        let Some(mut queue) = registry
+            .entity((*client_id).into())
+            .and_then(|entity| query.get_mut(entity).ok())
+        else {
+            continue;
+        };
+        if !queue.receive(event.sequence) {
+            continue;
+        }
+
+        // Server simulates the movement itself, so only inputs are checked.
+        match validation::check_input(&event.input) {
+            Ok(()) => {
+                let input = match match_state.get() {
+                    MatchState::Lobby => PlayerInput::default(),
+                    MatchState::Playing => event.input,
+                };
+                queue.push(event.sequence, input);
+            }
+            Err(violation) => {
+                queue.reject(event.sequence);
+                violations.send(MovementViolationEvent {
+                    client_id: ClientId::from(*client_id),
+                    violation,
+                });
+            }
+        }
+    }
+}
+
+fn simulate_remote(mut query: Query<(&mut CharacterVectors, &mut InputQueue, Has<Downed>)>) {
+    for (mut vectors, mut queue, downed) in &mut query {
+        queue.simulated_ticks += 1;
+        match queue.inputs.pop_front() {
+            // Downed players do not move, their clients predict the same.
+            Some((sequence, _)) if downed => {
+                PlayerInput::default().apply(&mut vectors);
+                queue.last_sequence = Some(sequence);
+            }
+            Some((sequence, input)) => {
+                input.apply(&mut vectors);
+                queue.last_sequence = Some(sequence);
+            }
+            None => PlayerInput::default().apply(&mut vectors),
+        }
+    }
+}
+
+/// Moves players back when the move simulated for them breaks movement limits, e.g. when
+/// physics pushed them through a wall.
+fn validate_simulated_moves(
+    mut query: Query<(
+        &Player,
+        &mut Transform,
+        &mut CharacterVectors,
+        &mut InputQueue,
+    )>,
+    context: Res<RapierContext>,
+    settings: Res<MovementValidationSettings>,
+) {
+    for (player, mut transform, mut vectors, mut queue) in &mut query {
+        let ticks = std::mem::take(&mut queue.simulated_ticks).max(1);
+        let Some(baseline) = queue.baseline else {
+            queue.baseline = Some(transform.translation);
+            continue;
+        };
+
+        match validation::check_move(&context, &settings, baseline, transform.translation, ticks) {
+            Ok(()) => queue.baseline = Some(transform.translation),
+            Err(invalid_move) => {
+                warn!("Simulated move of player {player} undone: {invalid_move:?}");
+                transform.translation = baseline;
+                vectors.velocity = Vec3::ZERO;
+            }
+        }
+    }
+}
+
+/// Sends state of players to their clients after the server simulated their new input.
+fn player_state_sender(
+    mut event: EventWriter<ToClients<PlayerStateServerEvent>>,
+    mut query: Query<(
+        &Player,
+        &Transform,
+        &CharacterVectors,
+        &mut InputQueue,
+        Option<&KinematicCharacterControllerOutput>,
+    )>,
+) {
+    for (player, transform, vectors, mut queue, output) in &mut query {
+        let Some(sequence) = queue.last_sequence else {
+            continue;
+        };
+        if queue.sent_sequence == Some(sequence) {
+            continue;
+        }
+        queue.sent_sequence = Some(sequence);
+
+        event.send(ToClients {
+            mode: SendMode::Direct(player.client_id.into()),
+            event: PlayerStateServerEvent {
+                sequence,
+                transform: (*transform).into(),
+                velocity: vectors.velocity,
+                grounded: output.map_or(false, |x| x.grounded),
+                rejected: queue.rejected,
+            },
+        });
+    }
+}
+
+/// Rewinds local player to the last state confirmed by the server and replays unacknowledged
+/// inputs on top of it.
+#[allow(clippy::type_complexity)]
+fn reconcile(
+    mut event: EventReader<PlayerStateServerEvent>,
+    mut context: ResMut<RapierContext>,
+    mut query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut CharacterVectors,
+            &mut InputHistory,
+            &KinematicCharacterController,
+        ),
+        With<LocalPlayer>,
+    >,
+) {
+    // States travel unreliably, only the newest one is applied.
+    let Some(state) =
+        event.read().reduce(
+            |newest, state| match is_newer(state.sequence, newest.sequence) {
+                true => state,
+                false => newest,
+            },
+        )
+    else {
+        return;
+    };
+
+    let Ok((entity, mut transform, mut vectors, mut history, controller)) = query.get_single_mut()
+    else {
+        return;
+    };
+    let Some((shape, shape_offset, shape_rotation)) = &controller.custom_shape else {
+        return;
+    };
+    let Some(pending) = history.acknowledge(state.sequence, state.rejected) else {
+        return;
+    };
+
+    let options = MoveShapeOptions {
+        up: controller.up,
+        offset: controller.offset,
+        slide: controller.slide,
+        autostep: controller.autostep,
+        max_slope_climb_angle: controller.max_slope_climb_angle,
+        min_slope_slide_angle: controller.min_slope_slide_angle,
+        apply_impulse_to_dynamic_bodies: false,
+        snap_to_ground: controller.snap_to_ground,
+    };
+    let filter = QueryFilter::default().exclude_rigid_body(entity);
+
+    let mut translation = Transform::from(state.transform.clone()).translation;
+    let mut replayed = CharacterVectors {
+        velocity: state.velocity,
+        ..vectors.clone()
+    };
+    let mut grounded = state.grounded;
+    for input in pending {
+        input.apply(&mut replayed);
+        if grounded {
+            replayed.velocity.y = 0.0;
+        }
+
+        let output = context.move_shape(
+            replayed.velocity,
+            shape,
+            translation + *shape_offset,
+            *shape_rotation,
+            0.0,
+            &options,
+            filter,
+            |_| {},
+        );
+        translation += output.effective_translation;
+        grounded = output.grounded;
+    }
+
+    if translation.distance(transform.translation) > RECONCILIATION_TOLERANCE {
+        transform.translation = translation;
+        vectors.velocity = replayed.velocity;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(x: f32) -> PlayerInput {
+        PlayerInput {
+            movement: Vec3::new(x, 0.0, 0.0),
+        }
+    }
+
+    fn queue_inputs(queue: &mut InputQueue, sequences: &[u32]) {
+        for &sequence in sequences {
+            if queue.receive(sequence) {
+                queue.push(sequence, input(sequence as f32));
+            }
+        }
+    }
+
+    fn queued(queue: &InputQueue) -> Vec<u32> {
+        queue.inputs.iter().map(|(x, _)| *x).collect()
+    }
+
+    #[test]
+    fn queue_keeps_order() {
+        let mut queue = InputQueue::default();
+        queue_inputs(&mut queue, &[0, 1, 2, 4]);
+        assert_eq!(queued(&queue), [0, 1, 2, 4]);
+
+        let (sequence, input) = queue.inputs.pop_front().unwrap();
+        assert_eq!(sequence, 0);
+        assert_eq!(input.movement.x, 0.0);
+    }
+
+    #[test]
+    fn queue_drops_duplicate_and_old_inputs() {
+        let mut queue = InputQueue::default();
+        queue_inputs(&mut queue, &[5, 5, 6, 3, 6, 7]);
+        assert_eq!(queued(&queue), [5, 6, 7]);
+
+        // Simulated and rejected inputs are not accepted again either.
+        queue.inputs.clear();
+        queue.reject(7);
+        queue_inputs(&mut queue, &[7, 2, 8]);
+        assert_eq!(queued(&queue), [8]);
+    }
+
+    #[test]
+    fn queue_wraps_sequences() {
+        let mut queue = InputQueue::default();
+        queue_inputs(&mut queue, &[u32::MAX - 1, u32::MAX, 0, u32::MAX, 1]);
+        assert_eq!(queued(&queue), [u32::MAX - 1, u32::MAX, 0, 1]);
+    }
+
+    #[test]
+    fn queue_drops_oldest_when_flooded() {
+        // Inputs sent during a hitch of the client arrive at once.
+        let mut queue = InputQueue::default();
+        let sequences: Vec<_> = (0..MAX_QUEUED_INPUTS as u32 + 3).collect();
+        queue_inputs(&mut queue, &sequences);
+
+        assert_eq!(queue.inputs.len(), MAX_QUEUED_INPUTS);
+        assert_eq!(queue.inputs.front().map(|x| x.0), Some(3));
+        assert_eq!(queue.rejected, None);
+    }
+
+    #[test]
+    fn replays_unacknowledged_inputs() {
+        let inputs: Vec<_> = (0..10).map(|x| input((x % 3) as f32 - 1.0)).collect();
+        let mut history = InputHistory {
+            // Sequences wrap around while predicting.
+            next_sequence: u32::MAX - 3,
+            ..default()
+        };
+
+        let mut predicted = CharacterVectors::default();
+        let mut sequences = Vec::new();
+        for input in &inputs {
+            input.apply(&mut predicted);
+            sequences.push(history.push(*input));
+        }
+
+        // Server simulated the first four inputs, replaying the rest on its state predicts the
+        // same as the client did.
+        let mut server = CharacterVectors::default();
+        for input in &inputs[..4] {
+            input.apply(&mut server);
+        }
+        let replayed: Vec<_> = history
+            .acknowledge(sequences[3], None)
+            .unwrap()
+            .copied()
+            .collect();
+        assert_eq!(replayed.len(), 6);
+        for input in &replayed {
+            input.apply(&mut server);
+        }
+        assert_eq!(server.velocity, predicted.velocity);
+
+        // States arriving late are ignored, they would bring back acknowledged inputs.
+        assert!(history.acknowledge(sequences[1], None).is_none());
+        assert!(history.acknowledge(sequences[3], None).is_none());
+        assert_eq!(history.acknowledge(sequences[9], None).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn rejected_inputs_are_not_replayed() {
+        let mut history = InputHistory::default();
+        for x in 0..8 {
+            history.push(input(x as f32));
+        }
+
+        let replayed: Vec<_> = history
+            .acknowledge(1, Some(4))
+            .unwrap()
+            .map(|x| x.movement.x)
+            .collect();
+        assert_eq!(replayed, [5.0, 6.0, 7.0]);
+
+        // Rejection is repeated in later states, older one does not drop anything more.
+        assert_eq!(history.acknowledge(5, Some(4)).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn queue_remembers_rejected_input() {
+        let mut queue = InputQueue::default();
+        queue_inputs(&mut queue, &[0, 1, 2]);
+        assert!(queue.receive(3));
+        queue.reject(3);
+        assert!(queue.inputs.is_empty());
+        assert_eq!(queue.rejected, Some(3));
+
+        queue_inputs(&mut queue, &[3, 4]);
+        assert_eq!(queued(&queue), [4]);
+        assert_eq!(queue.rejected, Some(3));
+    }
+}
@@ -2,7 +2,7 @@ use bevy::{prelude::*, window::PrimaryWindow};
 use bevy_replicon::replicon_core::replication_rules::AppReplicationExt;
 use serde::{Deserialize, Serialize};
 
-use crate::network::has_local_player;
+use crate::network::{has_local_player, protocol::ProtocolAppExt};
 
 use super::{LocalPlayer, Player};
 
@@ -11,6 +11,7 @@ pub struct InteractionPointPlugin;
 impl Plugin for InteractionPointPlugin {
     fn build(&self, app: &mut App) {
         app.replicate::<InteractionPoint>()
+            .add_protocol_type::<InteractionPoint>()
             .add_systems(PreUpdate, find.run_if(has_local_player));
     }
 }
@@ -58,7 +59,8 @@ fn find(
     }
 }
 
-fn get_interest_point(
+/// Point on the ground plane of the player under the cursor.
+pub fn get_interest_point(
     player: &Player,
     player_transform: &Transform,
     cameras: &Query<(&GlobalTransform, &Camera)>,
@@ -0,0 +1,50 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Align2, Color32},
+    EguiContexts,
+};
+
+use super::Player;
+
+/// Height above the center of the player at which its name is shown.
+const NAME_TAG_HEIGHT: f32 = 1.2;
+
+pub struct NameTagPlugin;
+
+impl Plugin for NameTagPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            draw_name_tags.after(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Draws names of players above their meshes.
+fn draw_name_tags(
+    mut ctx: EguiContexts,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    players: Query<(Entity, &Player, &GlobalTransform)>,
+) {
+    let Some((camera, camera_transform)) = cameras.iter().find(|(camera, _)| camera.is_active)
+    else {
+        return;
+    };
+
+    for (entity, player, transform) in &players {
+        let position = transform.translation() + Vec3::Y * NAME_TAG_HEIGHT;
+        let Some(viewport_position) = camera.world_to_viewport(camera_transform, position) else {
+            continue;
+        };
+
+        let [r, g, b, _] = player.color().as_rgba_u8();
+        egui::Area::new(("name_tag", entity))
+            .fixed_pos(egui::pos2(viewport_position.x, viewport_position.y))
+            .pivot(Align2::CENTER_BOTTOM)
+            .interactable(false)
+            .order(egui::Order::Background)
+            .show(ctx.ctx_mut(), |ui| {
+                ui.colored_label(Color32::from_rgb(r, g, b), player.to_string());
+            });
+    }
+}
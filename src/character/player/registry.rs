@@ -0,0 +1,78 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::{client::ClientSet, server::ServerSet};
+
+use crate::network::client::ClientId;
+
+use super::Player;
+
+pub struct RegistryPlugin;
+
+/// Set in which [`PlayerRegistry`] follows spawned, despawned and reconnected players, systems
+/// which look players up during `PreUpdate` run after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
+pub struct UpdatePlayerRegistry;
+
+impl Plugin for RegistryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerRegistry>()
+            .configure_sets(
+                PreUpdate,
+                UpdatePlayerRegistry
+                    .after(ClientSet::Receive)
+                    .after(ServerSet::Receive),
+            )
+            .add_systems(PreUpdate, update_registry.in_set(UpdatePlayerRegistry));
+    }
+}
+
+/// Entities of players by id of their client, on the server and on clients.
+///
+/// Entities may be despawned earlier than the registry notices, so lookups still have to
+/// handle missing entities.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct PlayerRegistry {
+    entities: HashMap<ClientId, Entity>,
+    clients: HashMap<Entity, ClientId>,
+}
+
+impl PlayerRegistry {
+    pub fn entity(&self, client_id: ClientId) -> Option<Entity> {
+        self.entities.get(&client_id).copied()
+    }
+
+    pub fn client_id(&self, entity: Entity) -> Option<ClientId> {
+        self.clients.get(&entity).copied()
+    }
+
+    fn insert(&mut self, client_id: ClientId, entity: Entity) {
+        self.remove(entity);
+        self.entities.insert(client_id, entity);
+        self.clients.insert(entity, client_id);
+    }
+
+    fn remove(&mut self, entity: Entity) {
+        if let Some(client_id) = self.clients.remove(&entity) {
+            if self.entities.get(&client_id) == Some(&entity) {
+                self.entities.remove(&client_id);
+            }
+        }
+    }
+}
+
+/// Follows players spawned locally or by replication, and clients which reconnected to their
+/// player under new id.
+fn update_registry(
+    mut registry: ResMut<PlayerRegistry>,
+    mut removed: RemovedComponents<Player>,
+    players: Query<(Entity, &Player), Changed<Player>>,
+) {
+    for entity in removed.read() {
+        registry.remove(entity);
+    }
+
+    for (entity, player) in &players {
+        if registry.client_id(entity) != Some(player.client_id) {
+            registry.insert(player.client_id, entity);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::*;
+use bevy_replicon::server::ServerSet;
+
+use crate::network::{client::ClientId, has_server, session::Disconnections};
+
+use super::input::{PlayerInput, DAMPING, SPEED};
+
+/// Highest horizontal distance which player is able to travel in a single fixed tick.
+const MAX_HORIZONTAL_STEP: f32 = SPEED * DAMPING / (1.0 - DAMPING);
+/// Tolerance for floating point errors in inputs and positions.
+const EPSILON: f32 = 0.001;
+
+pub struct ValidationPlugin;
+
+impl Plugin for ValidationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MovementValidationSettings>()
+            .init_resource::<MovementViolations>()
+            .add_event::<MovementViolationEvent>()
+            .add_systems(
+                PreUpdate,
+                record_violations
+                    .after(ServerSet::Receive)
+                    .run_if(has_server),
+            );
+    }
+}
+
+/// Limits of inputs sent by clients and of moves which the server simulates from them.
+#[derive(Debug, Clone, Resource)]
+pub struct MovementValidationSettings {
+    /// Multiplier of the highest possible speed which is still accepted, covers pushes by other
+    /// characters.
+    pub speed_tolerance: f32,
+    /// Highest rise of simulated position per unit of horizontal distance, covers slopes.
+    pub max_climb: f32,
+    /// Violation score after which client is kicked, `None` only records violations.
+    pub kick_threshold: Option<f32>,
+    /// Violation score forgiven per second.
+    pub decay: f32,
+}
+
+impl Default for MovementValidationSettings {
+    fn default() -> Self {
+        Self {
+            speed_tolerance: 1.5,
+            max_climb: 1.0,
+            kick_threshold: Some(20.0),
+            decay: 2.0,
+        }
+    }
+}
+
+/// Malformed input of the client, scored against it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Violation {
+    /// Movement of input is not finite or longer than one.
+    InvalidInput,
+}
+
+impl Violation {
+    fn score(&self) -> f32 {
+        match self {
+            Violation::InvalidInput => 5.0,
+        }
+    }
+}
+
+/// Move simulated by the server which breaks movement limits, it is undone without blaming the
+/// client, which only sends inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidMove {
+    /// Position is not finite.
+    NotFinite,
+    /// Player moved faster than possible.
+    Speed,
+    /// Player moved up against gravity.
+    Gravity,
+    /// Player moved through fixed collider.
+    Collision,
+}
+
+#[derive(Debug, Clone, Event)]
+pub struct MovementViolationEvent {
+    pub client_id: ClientId,
+    pub violation: Violation,
+}
+
+/// Movement violations recorded by the server per client.
+#[derive(Debug, Clone, Resource, Default)]
+pub struct MovementViolations {
+    records: HashMap<ClientId, ViolationRecord>,
+}
+
+impl MovementViolations {
+    pub fn get(&self, client_id: ClientId) -> Option<&ViolationRecord> {
+        self.records.get(&client_id)
+    }
+
+    /// Moves violations of the client to its new id after it reconnected.
+    pub fn transfer(&mut self, from: ClientId, to: ClientId) {
+        if let Some(record) = self.records.remove(&from) {
+            self.records.insert(to, record);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ViolationRecord {
+    /// Current score, decays over time.
+    pub score: f32,
+    /// Number of violations of each kind since client connected.
+    pub counts: HashMap<Violation, u32>,
+}
+
+impl ViolationRecord {
+    /// Forgives score for the elapsed seconds.
+    fn decay(&mut self, settings: &MovementValidationSettings, seconds: f32) {
+        self.score = (self.score - settings.decay * seconds).max(0.0);
+    }
+
+    /// Adds score of the violation, returns whether the client should be kicked.
+    fn add(&mut self, settings: &MovementValidationSettings, violation: Violation) -> bool {
+        self.score += violation.score();
+        *self.counts.entry(violation).or_default() += 1;
+        settings
+            .kick_threshold
+            .map_or(false, |threshold| self.score >= threshold)
+    }
+}
+
+pub fn check_input(input: &PlayerInput) -> Result<(), Violation> {
+    match input.movement.is_finite() && input.movement.length() <= 1.0 + EPSILON {
+        true => Ok(()),
+        false => Err(Violation::InvalidInput),
+    }
+}
+
+/// Checks move between two positions simulated by the server, `ticks` fixed ticks apart.
+pub fn check_move(
+    context: &RapierContext,
+    settings: &MovementValidationSettings,
+    from: Vec3,
+    to: Vec3,
+    ticks: u32,
+) -> Result<(), InvalidMove> {
+    check_step(settings, from, to, ticks)?;
+
+    let delta = to - from;
+    let distance = delta.length();
+    if distance > EPSILON
+        && context
+            .cast_ray(
+                from,
+                delta / distance,
+                distance,
+                true,
+                QueryFilter::only_fixed(),
+            )
+            .is_some()
+    {
+        return Err(InvalidMove::Collision);
+    }
+
+    Ok(())
+}
+
+/// Checks speed and climbing of the move, without colliders.
+fn check_step(
+    settings: &MovementValidationSettings,
+    from: Vec3,
+    to: Vec3,
+    ticks: u32,
+) -> Result<(), InvalidMove> {
+    if !to.is_finite() {
+        return Err(InvalidMove::NotFinite);
+    }
+
+    let delta = to - from;
+    let horizontal = Vec2::new(delta.x, delta.z).length();
+    if horizontal > MAX_HORIZONTAL_STEP * ticks as f32 * settings.speed_tolerance + EPSILON {
+        return Err(InvalidMove::Speed);
+    }
+    if delta.y > horizontal * settings.max_climb + EPSILON {
+        return Err(InvalidMove::Gravity);
+    }
+
+    Ok(())
+}
+
+fn record_violations(
+    mut event: EventReader<MovementViolationEvent>,
+    mut violations: ResMut<MovementViolations>,
+    mut disconnections: ResMut<Disconnections>,
+    settings: Res<MovementValidationSettings>,
+    time: Res<Time>,
+) {
+    for record in violations.records.values_mut() {
+        record.decay(&settings, time.delta_seconds());
+    }
+
+    for MovementViolationEvent {
+        client_id,
+        violation,
+    } in event.read()
+    {
+        let record = violations.records.entry(*client_id).or_default();
+        let kick = record.add(&settings, *violation);
+
+        warn!(
+            "Player {client_id} sent invalid input: {violation:?}, score {:.1}",
+            record.score
+        );
+
+        if kick {
+            warn!("Player {client_id} kicked for movement violations.");
+            disconnections.kick(*client_id, "Too many movement violations");
+            violations.records.remove(client_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: f32 = 1.0 / 60.0;
+
+    /// Scores inputs received every tick and forgives score in between, as the server does,
+    /// returns the record and the tick at which the client was kicked.
+    fn play(inputs: &[Vec3]) -> (ViolationRecord, Option<usize>) {
+        let settings = MovementValidationSettings::default();
+        let mut record = ViolationRecord::default();
+        for (tick, movement) in inputs.iter().enumerate() {
+            record.decay(&settings, TICK);
+            let input = PlayerInput {
+                movement: *movement,
+            };
+            if let Err(violation) = check_input(&input) {
+                if record.add(&settings, violation) {
+                    return (record, Some(tick));
+                }
+            }
+        }
+        (record, None)
+    }
+
+    #[test]
+    fn legitimate_inputs_score_zero() {
+        let directions = [Vec3::ZERO, Vec3::X, Vec3::NEG_Z, Vec3::new(1.0, 0.0, 1.0)];
+        let inputs: Vec<_> = (0..600)
+            .map(|x| directions[x / 50 % directions.len()].normalize_or_zero())
+            .collect();
+        let (record, kicked) = play(&inputs);
+        assert_eq!(record.score, 0.0);
+        assert!(record.counts.is_empty());
+        assert_eq!(kicked, None);
+    }
+
+    #[test]
+    fn malformed_inputs_cross_threshold() {
+        for movement in [Vec3::NAN, Vec3::X * f32::INFINITY, Vec3::new(2.0, 0.0, 0.0)] {
+            assert_eq!(
+                check_input(&PlayerInput { movement }),
+                Err(Violation::InvalidInput)
+            );
+        }
+
+        // Single malformed input is forgiven, repeated ones are kicked.
+        let inputs: Vec<_> = (0..600)
+            .map(|x| match x % 10 {
+                0 => Vec3::X * 5.0,
+                _ => Vec3::X,
+            })
+            .collect();
+        let (record, kicked) = play(&inputs[..10]);
+        assert_eq!(kicked, None);
+        assert_eq!(record.counts.get(&Violation::InvalidInput), Some(&1));
+        assert!(record.score < Violation::InvalidInput.score());
+        let (record, kicked) = play(&inputs);
+        assert!(kicked.is_some());
+        let settings = MovementValidationSettings::default();
+        assert!(record.score >= settings.kick_threshold.unwrap());
+    }
+
+    #[test]
+    fn decay_forgives_violations() {
+        let settings = MovementValidationSettings::default();
+        let threshold = settings.kick_threshold.unwrap();
+        let score = Violation::InvalidInput.score();
+        let mut record = ViolationRecord::default();
+        while record.score + score < threshold {
+            assert!(!record.add(&settings, Violation::InvalidInput));
+        }
+
+        record.decay(&settings, score / settings.decay);
+        assert!(!record.add(&settings, Violation::InvalidInput));
+        record.decay(&settings, threshold / settings.decay);
+        assert_eq!(record.score, 0.0);
+        assert!(!record.add(&settings, Violation::InvalidInput));
+    }
+
+    #[test]
+    fn without_threshold_never_kicks() {
+        let settings = MovementValidationSettings {
+            kick_threshold: None,
+            ..default()
+        };
+        let mut record = ViolationRecord::default();
+        for _ in 0..100 {
+            assert!(!record.add(&settings, Violation::InvalidInput));
+        }
+        assert_eq!(record.counts.get(&Violation::InvalidInput), Some(&100));
+    }
+
+    #[test]
+    fn simulated_moves_within_limits() {
+        let settings = MovementValidationSettings::default();
+        let step = Vec3::X * MAX_HORIZONTAL_STEP;
+        assert_eq!(check_step(&settings, Vec3::ZERO, step, 1), Ok(()));
+        assert_eq!(check_step(&settings, Vec3::ZERO, step * 3.0, 3), Ok(()));
+        // Walking up a slope and falling are allowed as well.
+        let slope = step + Vec3::Y * MAX_HORIZONTAL_STEP * 0.5;
+        assert_eq!(check_step(&settings, Vec3::ZERO, slope, 1), Ok(()));
+        assert_eq!(check_step(&settings, Vec3::ZERO, Vec3::NEG_Y, 1), Ok(()));
+    }
+
+    #[test]
+    fn simulated_moves_beyond_limits() {
+        let settings = MovementValidationSettings::default();
+        let step = Vec3::X * MAX_HORIZONTAL_STEP;
+        assert_eq!(
+            check_step(&settings, Vec3::ZERO, step * 3.0, 1),
+            Err(InvalidMove::Speed)
+        );
+        assert_eq!(
+            check_step(&settings, Vec3::ZERO, Vec3::new(50.0, 0.0, 0.0), 10),
+            Err(InvalidMove::Speed)
+        );
+        assert_eq!(
+            check_step(&settings, Vec3::ZERO, Vec3::Y, 1),
+            Err(InvalidMove::Gravity)
+        );
+        assert_eq!(
+            check_step(&settings, Vec3::ZERO, Vec3::NAN, 1),
+            Err(InvalidMove::NotFinite)
+        );
+    }
+}
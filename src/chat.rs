@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{
+    egui::{self, Align2, Color32},
+    EguiContexts, EguiPlugin,
+};
+use bevy_replicon::{
+    client::ClientSet,
+    network_event::{
+        client_event::{ClientEventAppExt, FromClient},
+        server_event::{SendMode, ServerEventAppExt, ToClients},
+        EventType,
+    },
+    prelude::*,
+    server::ServerSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::player::{
+        registry::{PlayerRegistry, UpdatePlayerRegistry},
+        Player,
+    },
+    network::{
+        client::{ClientId, ClientSession},
+        has_local_player, has_server,
+        server::Server,
+    },
+};
+
+/// Maximum number of characters in a single message.
+pub const MAX_MESSAGE_LENGTH: usize = 200;
+/// Number of messages kept in the scrollback.
+const HISTORY_LENGTH: usize = 100;
+/// Number of messages player can send at once, before being limited by the refill rate.
+const RATE_LIMIT_BURST: f32 = 5.0;
+/// Number of messages per second allowed to every player over a longer time.
+const RATE_LIMIT_REFILL: f32 = 0.5;
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_client_event::<ChatClientEvent>(EventType::Ordered)
+            .add_server_event::<ChatServerEvent>(EventType::Ordered)
+            .init_resource::<ChatHistory>()
+            .init_resource::<ChatRateLimits>()
+            .add_systems(
+                PreUpdate,
+                (
+                    clear_chat.run_if(
+                        resource_added::<ClientSession>().or_else(resource_added::<Server>()),
+                    ),
+                    chat_server_handler
+                        .after(ServerSet::Receive)
+                        .after(UpdatePlayerRegistry)
+                        .run_if(has_server),
+                    receive_messages.after(ClientSet::Receive),
+                )
+                    .chain(),
+            );
+
+        // Headless server only relays messages.
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.init_resource::<ChatUiState>()
+                .add_systems(Update, ui.run_if(has_local_player));
+        }
+    }
+}
+
+/// Message typed by the player, sent to the server which relays it to everyone.
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
+struct ChatClientEvent {
+    text: String,
+}
+
+/// Message shown in the chat of every player.
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
+pub struct ChatServerEvent {
+    /// Name of the player who sent the message, `None` for messages of the server.
+    pub sender: Option<String>,
+    pub text: String,
+}
+
+impl ChatServerEvent {
+    /// Message of the server itself, such as players joining and leaving.
+    pub fn system(text: impl Into<String>) -> ToClients<Self> {
+        ToClients {
+            mode: SendMode::Broadcast,
+            event: Self {
+                sender: None,
+                text: text.into(),
+            },
+        }
+    }
+}
+
+/// Messages received in the current session, the oldest first.
+#[derive(Resource, Default)]
+pub struct ChatHistory {
+    messages: VecDeque<ChatServerEvent>,
+}
+
+/// Message typed by the local player.
+#[derive(Resource, Default)]
+pub struct ChatUiState {
+    /// Whether the chat has keyboard focus, so the keyboard does not move the character.
+    pub typing: bool,
+    text: String,
+    focus: bool,
+}
+
+/// Remaining number of messages which each client can send right now.
+#[derive(Resource, Default)]
+struct ChatRateLimits {
+    allowance: HashMap<ClientId, (f32, f32)>,
+}
+
+impl ChatRateLimits {
+    /// Consumes single message from the allowance of the client, returns false when exhausted.
+    fn consume(&mut self, client_id: ClientId, now: f32) -> bool {
+        let (allowance, last_update) = self
+            .allowance
+            .entry(client_id)
+            .or_insert((RATE_LIMIT_BURST, now));
+        *allowance = (*allowance + (now - *last_update) * RATE_LIMIT_REFILL).min(RATE_LIMIT_BURST);
+        *last_update = now;
+
+        if *allowance < 1.0 {
+            return false;
+        }
+        *allowance -= 1.0;
+        true
+    }
+}
+
+/// Trims the message and removes characters which do not belong to it.
+fn sanitize_message(text: &str) -> String {
+    text.trim()
+        .chars()
+        .filter(|x| !x.is_control())
+        .take(MAX_MESSAGE_LENGTH)
+        .collect()
+}
+
+fn clear_chat(mut history: ResMut<ChatHistory>, mut limits: ResMut<ChatRateLimits>) {
+    history.messages.clear();
+    limits.allowance.clear();
+}
+
+fn chat_server_handler(
+    mut chat_event: EventReader<FromClient<ChatClientEvent>>,
+    mut message_event: EventWriter<ToClients<ChatServerEvent>>,
+    mut limits: ResMut<ChatRateLimits>,
+    players: Query<&Player>,
+    registry: Res<PlayerRegistry>,
+    time: Res<Time>,
+) {
+    for FromClient { client_id, event } in chat_event.read() {
+        let Some(player) = registry
+            .entity((*client_id).into())
+            .and_then(|entity| players.get(entity).ok())
+        else {
+            continue;
+        };
+
+        let text = sanitize_message(&event.text);
+        if text.is_empty() {
+            continue;
+        }
+
+        if !limits.consume(player.client_id, time.elapsed_seconds()) {
+            message_event.send(ToClients {
+                mode: SendMode::Direct(*client_id),
+                event: ChatServerEvent {
+                    sender: None,
+                    text: String::from("You are sending messages too fast."),
+                },
+            });
+            continue;
+        }
+
+        info!("{player}: {text}");
+        message_event.send(ToClients {
+            mode: SendMode::Broadcast,
+            event: ChatServerEvent {
+                sender: Some(player.to_string()),
+                text,
+            },
+        });
+    }
+}
+
+fn receive_messages(mut event: EventReader<ChatServerEvent>, mut history: ResMut<ChatHistory>) {
+    for message in event.read() {
+        if history.messages.len() >= HISTORY_LENGTH {
+            history.messages.pop_front();
+        }
+        history.messages.push_back(message.clone());
+    }
+}
+
+fn ui(
+    mut ctx: EguiContexts,
+    mut event: EventWriter<ChatClientEvent>,
+    mut state: ResMut<ChatUiState>,
+    history: Res<ChatHistory>,
+    input: Res<Input<KeyCode>>,
+) {
+    // Enter starts typing when the chat does not have focus yet.
+    if !state.typing && input.just_pressed(KeyCode::Return) {
+        state.focus = true;
+    }
+
+    egui::Window::new("Chat")
+        .anchor(Align2::LEFT_BOTTOM, [8.0, -8.0])
+        .default_width(320.0)
+        .resizable(false)
+        .show(ctx.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(160.0)
+                .stick_to_bottom(true)
+                .auto_shrink([false, true])
+                .show(ui, |ui| {
+                    for message in &history.messages {
+                        match &message.sender {
+                            Some(sender) => ui.label(format!("{sender}: {}", message.text)),
+                            None => ui.colored_label(Color32::YELLOW, &message.text),
+                        };
+                    }
+                });
+
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut state.text)
+                    .char_limit(MAX_MESSAGE_LENGTH)
+                    .hint_text("Press Enter to chat"),
+            );
+            if state.focus {
+                response.request_focus();
+                state.focus = false;
+            }
+
+            if response.lost_focus() && ui.input(|x| x.key_pressed(egui::Key::Enter)) {
+                let text = std::mem::take(&mut state.text);
+                if !text.trim().is_empty() {
+                    event.send(ChatClientEvent { text });
+                }
+            }
+            state.typing = response.has_focus();
+        });
+}
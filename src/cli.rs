@@ -0,0 +1,166 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use bevy::prelude::*;
+use clap::{Parser, Subcommand};
+
+use crate::network::{
+    authentication::{self, Authentication, TokenIssuer},
+    conditioner::LinkConditions,
+    config::{self, NetworkConfig},
+    network_error::NetworkError,
+    permissions::Roles,
+    DEFAULT_MAX_CLIENTS, DEFAULT_PORT, DEFAULT_RECONNECT_WINDOW, MAX_TICK_RATE,
+};
+
+/// Command line arguments of the game.
+#[derive(Debug, Clone, Parser, Resource)]
+#[command(version, about = "Glass ballista game thing")]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Runs dedicated server without window, rendering and local player.
+    #[arg(long)]
+    pub headless: bool,
+    /// Port on which dedicated server listens.
+    #[arg(long, default_value_t = DEFAULT_PORT)]
+    pub port: u16,
+    /// Maximum number of clients connected to the server at the same time, defaults to the
+    /// network config or 2.
+    #[arg(long)]
+    pub max_clients: Option<usize>,
+    /// Connects to the server at startup, given as address with optional port.
+    #[arg(long, value_name = "ADDRESS", conflicts_with_all = ["host", "headless"])]
+    pub connect: Option<String>,
+    /// Hosts the game with local player on the given port at startup.
+    #[arg(long, value_name = "PORT", conflicts_with = "headless")]
+    pub host: Option<u16>,
+    /// Maximum number of replication ticks per second.
+    #[arg(long, default_value_t = MAX_TICK_RATE)]
+    pub tick_rate: u16,
+    /// Seconds during which lost connection is reconnected, and the server keeps player of it.
+    #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_RECONNECT_WINDOW)]
+    pub reconnect_window: f32,
+    /// Network config file of the server, with private key used to authenticate clients.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Connect token issued by the server, used to authenticate this client.
+    #[arg(long, value_name = "PATH")]
+    pub token: Option<PathBuf>,
+    /// File with players banned from the server, created on first ban.
+    #[arg(long, value_name = "PATH", default_value = "bans.toml")]
+    pub bans: PathBuf,
+    /// Client id of player allowed to run developer commands, in addition to admins from the
+    /// network config.
+    #[arg(long = "admin", value_name = "CLIENT_ID")]
+    pub admins: Vec<u64>,
+    /// File to which every command executed on the server is appended.
+    #[arg(long, value_name = "PATH", default_value = "audit.log")]
+    pub audit_log: PathBuf,
+    /// Milliseconds of simulated latency added to every packet, in both directions.
+    #[arg(long, value_name = "MS", default_value_t = 0.0)]
+    pub latency: f32,
+    /// Maximum milliseconds of simulated random delay added on top of the latency.
+    #[arg(long, value_name = "MS", default_value_t = 0.0)]
+    pub jitter: f32,
+    /// Percentage of packets dropped by the network simulator.
+    #[arg(long, value_name = "PERCENT", default_value_t = 0.0)]
+    pub packet_loss: f32,
+    /// Percentage of packets sent twice by the network simulator.
+    #[arg(long, value_name = "PERCENT", default_value_t = 0.0)]
+    pub duplication: f32,
+    /// Disables authentication, only for development.
+    #[arg(long)]
+    pub insecure: bool,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Generates new private key for the network config file.
+    GenerateKey,
+    /// Issues connect token for a single client, signed by private key from the network config.
+    IssueToken {
+        /// Network config file of the server.
+        #[arg(long, value_name = "PATH")]
+        config: PathBuf,
+        /// File to which connect token is written.
+        #[arg(long, value_name = "PATH")]
+        output: PathBuf,
+        /// Address of the server, defaults to public addresses from the network config.
+        #[arg(long = "server", value_name = "ADDRESS")]
+        server_addresses: Vec<SocketAddr>,
+        /// Number of seconds after which token can no longer be used to connect.
+        #[arg(long, default_value_t = 3600)]
+        expire_seconds: u64,
+    },
+}
+
+impl Args {
+    pub fn max_clients(&self, config: &NetworkConfig) -> usize {
+        self.max_clients
+            .or(config.max_clients)
+            .unwrap_or(DEFAULT_MAX_CLIENTS)
+    }
+
+    pub fn roles(&self, config: &NetworkConfig) -> Roles {
+        Roles::new(config.admins.iter().chain(&self.admins).copied())
+    }
+
+    /// Network simulator is enabled when any of the conditions is set.
+    pub fn link_conditions(&self) -> LinkConditions {
+        let conditions = LinkConditions {
+            enabled: false,
+            latency: self.latency.max(0.0),
+            jitter: self.jitter.max(0.0),
+            loss: (self.packet_loss / 100.0).clamp(0.0, 1.0),
+            duplication: (self.duplication / 100.0).clamp(0.0, 1.0),
+        };
+        LinkConditions {
+            enabled: conditions.latency > 0.0
+                || conditions.jitter > 0.0
+                || conditions.loss > 0.0
+                || conditions.duplication > 0.0,
+            ..conditions
+        }
+    }
+
+    pub fn authentication(&self) -> Result<Authentication, NetworkError> {
+        let config = match &self.config {
+            Some(path) => NetworkConfig::load(path)?,
+            None => NetworkConfig::default(),
+        };
+
+        Ok(Authentication {
+            insecure: self.insecure,
+            config,
+            connect_token: self.token.clone(),
+        })
+    }
+}
+
+impl Command {
+    pub fn run(&self) -> Result<(), NetworkError> {
+        match self {
+            Command::GenerateKey => {
+                let private_key = bevy_replicon::renet::transport::generate_random_bytes();
+                println!("private_key = \"{}\"", config::encode_key(&private_key));
+            }
+            Command::IssueToken {
+                config,
+                output,
+                server_addresses,
+                expire_seconds,
+            } => {
+                let config = NetworkConfig::load(config)?;
+                let issuer = TokenIssuer::new(&config, server_addresses.clone(), *expire_seconds)?;
+                let connect_token = issuer.issue()?;
+                authentication::write_connect_token(output, &connect_token)?;
+                println!(
+                    "Issued connect token for client #{} to {}",
+                    connect_token.client_id,
+                    output.display()
+                );
+            }
+        }
+        Ok(())
+    }
+}
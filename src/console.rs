@@ -0,0 +1,384 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::BufRead,
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+    thread,
+};
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts, EguiPlugin};
+use bevy_replicon::{
+    network_event::server_event::ToClients, prelude::*, renet::transport::NetcodeServerTransport,
+};
+
+use crate::{
+    character::player::{self, registry::PlayerRegistry, Player},
+    chat::ChatServerEvent,
+    developer_tools::spawn::{self, EnemyKind},
+    network::{
+        bans::{Ban, BanList},
+        client::ClientId,
+        has_server,
+        network_error::NetworkError,
+        permissions::{AuditLog, AuditOutcome, Role, Roles},
+        session::Disconnections,
+    },
+};
+
+/// Number of output lines kept in the console window.
+const LOG_LENGTH: usize = 200;
+/// Range of fixed timestep accepted by the `timestep` command, same as in the Time tool.
+const TIMESTEP_RANGE: std::ops::RangeInclusive<f64> = 8.0..=260.0;
+
+pub struct ConsolePlugin {
+    /// Whether commands are read from the standard input, used by headless server.
+    pub stdin: bool,
+}
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ConsoleCommandEvent>()
+            .init_resource::<ConsoleLog>()
+            .add_systems(Update, execute_commands.run_if(has_server));
+
+        if self.stdin {
+            app.insert_resource(StdinCommands::spawn())
+                .add_systems(Update, read_stdin.before(execute_commands));
+        }
+
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.init_resource::<ConsoleUiState>()
+                .add_systems(Update, ui.before(execute_commands).run_if(has_server));
+        }
+    }
+}
+
+/// Who issued the command, the output is sent back to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandSource {
+    /// Standard input of headless server.
+    Stdin,
+    /// Console window of the player hosting the game.
+    Host,
+}
+
+impl fmt::Display for CommandSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandSource::Stdin => write!(f, "Server console"),
+            CommandSource::Host => write!(f, "Host"),
+        }
+    }
+}
+
+/// Single line of admin command, executed on the server.
+#[derive(Debug, Clone, Event)]
+pub struct ConsoleCommandEvent {
+    pub source: CommandSource,
+    pub line: String,
+}
+
+/// Output of commands issued from the console window.
+#[derive(Resource, Default)]
+pub struct ConsoleLog {
+    lines: VecDeque<String>,
+}
+
+impl ConsoleLog {
+    /// Sends output of the command back to whoever issued it.
+    pub fn respond(&mut self, source: CommandSource, text: impl Into<String>) {
+        let text = text.into();
+        match source {
+            CommandSource::Stdin => println!("{text}"),
+            CommandSource::Host => {
+                if self.lines.len() >= LOG_LENGTH {
+                    self.lines.pop_front();
+                }
+                self.lines.push_back(text);
+            }
+        }
+    }
+}
+
+#[derive(Resource, Default)]
+struct ConsoleUiState {
+    line: String,
+}
+
+/// Lines read from the standard input by a separate thread, which blocks on reading.
+#[derive(Resource)]
+struct StdinCommands(Mutex<Receiver<String>>);
+
+impl StdinCommands {
+    fn spawn() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self(Mutex::new(receiver))
+    }
+}
+
+#[derive(Debug)]
+enum ConsoleError {
+    UnknownCommand(String),
+    MissingArgument(&'static str),
+    InvalidArgument(&'static str),
+    PlayerNotFound(String),
+    Network(NetworkError),
+}
+
+impl fmt::Display for ConsoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsoleError::UnknownCommand(command) => {
+                write!(f, "Unknown command {command}, type help for the list")
+            }
+            ConsoleError::MissingArgument(name) => write!(f, "Missing argument {name}"),
+            ConsoleError::InvalidArgument(name) => write!(f, "Invalid argument {name}"),
+            ConsoleError::PlayerNotFound(player) => write!(f, "No player {player}"),
+            ConsoleError::Network(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl From<NetworkError> for ConsoleError {
+    fn from(err: NetworkError) -> Self {
+        Self::Network(err)
+    }
+}
+
+const HELP: &str = "\
+list                        players with client id, role and ping
+role <client id> <role>     makes the player admin or player
+kick <client id> [reason]   disconnects the player
+ban <client id> [reason]    disconnects the player and refuses it from now on
+unban <client id | ip>      removes the ban
+say <text>                  sends server message to the chat
+spawn <enemy>               spawns enemy at the center of the level
+timestep <hz>               changes fixed timestep of the server and clients";
+
+fn read_stdin(mut command_event: EventWriter<ConsoleCommandEvent>, stdin: Res<StdinCommands>) {
+    let receiver = stdin.0.lock().unwrap();
+    while let Ok(line) = receiver.try_recv() {
+        command_event.send(ConsoleCommandEvent {
+            source: CommandSource::Stdin,
+            line,
+        });
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn execute_commands(
+    mut commands: Commands,
+    mut command_event: EventReader<ConsoleCommandEvent>,
+    mut chat_event: EventWriter<ToClients<ChatServerEvent>>,
+    mut log: ResMut<ConsoleLog>,
+    mut disconnections: ResMut<Disconnections>,
+    mut ban_list: ResMut<BanList>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+    mut roles: ResMut<Roles>,
+    mut audit_log: ResMut<AuditLog>,
+    server: Option<Res<RenetServer>>,
+    transport: Option<Res<NetcodeServerTransport>>,
+    players: Query<&Player>,
+    registry: Res<PlayerRegistry>,
+) {
+    for ConsoleCommandEvent { source, line } in command_event.read() {
+        let mut args = line.split_whitespace();
+        let Some(command) = args.next() else {
+            continue;
+        };
+        if *source == CommandSource::Host {
+            log.respond(*source, format!("> {line}"));
+        }
+        let result = match command {
+            "help" => Ok(HELP.to_string()),
+            "list" => Ok(list_players(&players, &roles, server.as_deref())),
+            "role" => find_player(&players, &registry, args.next()).and_then(|player| {
+                let role = match args.next() {
+                    Some("player") => Role::Player,
+                    Some("admin") => Role::Admin,
+                    Some(_) => return Err(ConsoleError::InvalidArgument("role")),
+                    None => return Err(ConsoleError::MissingArgument("role")),
+                };
+                roles
+                    .set_role(player.client_id, role)
+                    .map_err(|_| ConsoleError::InvalidArgument("role"))?;
+                Ok(format!("{player} is now {role}"))
+            }),
+            "kick" => find_player(&players, &registry, args.next()).map(|player| {
+                let reason = rest(args).unwrap_or_else(|| String::from("Kicked by the server"));
+                disconnections.kick(player.client_id, reason);
+                format!("Kicked {player}")
+            }),
+            "ban" => find_player(&players, &registry, args.next()).and_then(|player| {
+                let reason = rest(args).unwrap_or_else(|| String::from("Banned by the server"));
+                let ip = transport
+                    .as_ref()
+                    .and_then(|x| x.client_addr(player.client_id.into()))
+                    .map(|x| x.ip());
+                ban_list.ban(Ban {
+                    client_id: player.client_id.raw(),
+                    ip,
+                    reason: reason.clone(),
+                })?;
+                disconnections.kick(player.client_id, format!("Banned: {reason}"));
+                Ok(format!("Banned {player}"))
+            }),
+            "unban" => unban(&mut ban_list, args.next()),
+            "say" => rest(args)
+                .ok_or(ConsoleError::MissingArgument("text"))
+                .map(|text| {
+                    chat_event.send(ChatServerEvent::system(format!("[Server] {text}")));
+                    String::new()
+                }),
+            "spawn" => args
+                .next()
+                .ok_or(ConsoleError::MissingArgument("enemy"))
+                .and_then(|name| {
+                    EnemyKind::from_name(name).ok_or(ConsoleError::InvalidArgument("enemy"))
+                })
+                .map(|kind| {
+                    spawn::spawn_enemy(
+                        &mut commands,
+                        kind,
+                        Transform::from_translation(player::SPAWN_POINT),
+                    );
+                    format!("Spawned {kind:?}")
+                }),
+            "timestep" => args
+                .next()
+                .ok_or(ConsoleError::MissingArgument("hz"))
+                .and_then(|hz| {
+                    f64::from_str(hz)
+                        .ok()
+                        .filter(|hz| TIMESTEP_RANGE.contains(hz))
+                        .ok_or(ConsoleError::InvalidArgument("hz"))
+                })
+                .map(|hz| {
+                    fixed_time.set_timestep_hz(hz);
+                    format!("Timestep set to {hz} Hz")
+                }),
+            _ => Err(ConsoleError::UnknownCommand(command.to_string())),
+        };
+
+        // Commands which only read state of the server are not audited. Console is available
+        // only on the server, so every command is issued by the host.
+        if !matches!(command, "help" | "list") {
+            let outcome = match result {
+                Ok(_) => AuditOutcome::Executed,
+                Err(_) => AuditOutcome::Failed,
+            };
+            audit_log.record(&source.to_string(), Role::Host, line, outcome);
+        }
+
+        match result {
+            Ok(output) if output.is_empty() => {}
+            Ok(output) => log.respond(*source, output),
+            Err(err) => log.respond(*source, format!("Error: {err}")),
+        }
+    }
+}
+
+/// Joins remaining arguments back into single text.
+fn rest<'a>(args: impl Iterator<Item = &'a str>) -> Option<String> {
+    let text = args.collect::<Vec<_>>().join(" ");
+    (!text.is_empty()).then_some(text)
+}
+
+fn find_player<'a>(
+    players: &'a Query<&Player>,
+    registry: &PlayerRegistry,
+    client_id: Option<&str>,
+) -> Result<&'a Player, ConsoleError> {
+    let client_id = client_id.ok_or(ConsoleError::MissingArgument("client id"))?;
+    let raw = u64::from_str(client_id.trim_start_matches('#'))
+        .map_err(|_| ConsoleError::InvalidArgument("client id"))?;
+    registry
+        .entity(ClientId::from_raw(raw))
+        .filter(|_| raw != SERVER_ID.raw())
+        .and_then(|entity| players.get(entity).ok())
+        .ok_or_else(|| ConsoleError::PlayerNotFound(client_id.to_string()))
+}
+
+fn list_players(players: &Query<&Player>, roles: &Roles, server: Option<&RenetServer>) -> String {
+    let mut lines = vec![format!("{} players", players.iter().count())];
+    for player in players {
+        let ping = match player.client_id.raw() == SERVER_ID.raw() {
+            true => String::from("host"),
+            false => server
+                .and_then(|x| x.network_info(player.client_id.into()).ok())
+                .map_or_else(
+                    || String::from("disconnected"),
+                    |x| format!("{:.0} ms", x.rtt * 1000.0),
+                ),
+        };
+        lines.push(format!(
+            "{:>24} {player} {} {ping}",
+            player.client_id.raw(),
+            roles.role(player.client_id)
+        ));
+    }
+    lines.join("\n")
+}
+
+fn unban(ban_list: &mut BanList, value: Option<&str>) -> Result<String, ConsoleError> {
+    let value = value.ok_or(ConsoleError::MissingArgument("client id or ip"))?;
+    let removed = match IpAddr::from_str(value) {
+        Ok(ip) => ban_list.unban(None, Some(ip))?,
+        Err(_) => {
+            let raw = u64::from_str(value.trim_start_matches('#'))
+                .map_err(|_| ConsoleError::InvalidArgument("client id or ip"))?;
+            ban_list.unban(Some(ClientId::from_raw(raw)), None)?
+        }
+    };
+    Ok(format!("Removed {removed} bans"))
+}
+
+fn ui(
+    mut ctx: EguiContexts,
+    mut command_event: EventWriter<ConsoleCommandEvent>,
+    mut state: ResMut<ConsoleUiState>,
+    log: Res<ConsoleLog>,
+) {
+    egui::Window::new("Server console")
+        .default_open(false)
+        .show(ctx.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(240.0)
+                .stick_to_bottom(true)
+                .auto_shrink([false, true])
+                .show(ui, |ui| {
+                    for line in &log.lines {
+                        ui.monospace(line);
+                    }
+                });
+
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut state.line)
+                    .code_editor()
+                    .hint_text("help"),
+            );
+            if response.lost_focus() && ui.input(|x| x.key_pressed(egui::Key::Enter)) {
+                command_event.send(ConsoleCommandEvent {
+                    source: CommandSource::Host,
+                    line: std::mem::take(&mut state.line),
+                });
+                response.request_focus();
+            }
+        });
+}
@@ -0,0 +1,96 @@
+use std::collections::VecDeque;
+
+use bevy_egui::egui::{self, Color32};
+use egui_plot::{Legend, Line, Plot, PlotPoints, PlotUi};
+
+/// Line of a graph, its last value is shown below the plot.
+pub struct GraphLine<'a> {
+    pub name: &'a str,
+    pub data: &'a VecDeque<f64>,
+    pub color: Option<Color32>,
+    /// Fills the area between the line and this value.
+    pub fill: Option<f32>,
+}
+
+impl<'a> GraphLine<'a> {
+    pub fn new(name: &'a str, data: &'a VecDeque<f64>) -> Self {
+        Self {
+            name,
+            data,
+            color: None,
+            fill: None,
+        }
+    }
+
+    pub fn with_color(mut self, color: Color32) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn with_fill(mut self, fill: f32) -> Self {
+        self.fill = Some(fill);
+        self
+    }
+}
+
+/// Writes collapsible plot of the lines with their last values, `labels` adds more of them.
+pub fn write_graph(
+    ui: &mut egui::Ui,
+    lines: &[GraphLine],
+    heading: &str,
+    id: &str,
+    unit: &str,
+    labels: impl FnOnce(&mut egui::Ui),
+) {
+    let length = lines.iter().map(|x| x.data.len()).max().unwrap_or(0);
+
+    ui.collapsing(heading, |ui| {
+        Plot::new(id)
+            .include_x(0.0)
+            .include_x(length as f64)
+            .include_y(0.0)
+            .view_aspect(3.0)
+            .height(128.0)
+            .legend(Legend::default())
+            .show(ui, |plot_ui| {
+                for line in lines {
+                    write_line(plot_ui, line);
+                }
+            });
+
+        ui.horizontal_wrapped(|ui| {
+            for line in lines {
+                let Some(value) = line.data.back() else {
+                    continue;
+                };
+                let text = format!("{}: {value:.2} {unit}", line.name);
+                match line.color {
+                    Some(color) => ui.colored_label(color, text),
+                    None => ui.label(text),
+                };
+            }
+            labels(ui);
+        });
+    });
+}
+
+fn write_line(plot_ui: &mut PlotUi, line: &GraphLine) {
+    if line.data.is_empty() {
+        return;
+    }
+
+    let points = PlotPoints::from_parametric_callback(
+        |x| (x, line.data[x as usize]),
+        0.0..=(line.data.len() - 1) as f64,
+        line.data.len(),
+    );
+
+    let mut plot_line = Line::new(points).name(line.name);
+    if let Some(color) = line.color {
+        plot_line = plot_line.color(color);
+    }
+    if let Some(fill) = line.fill {
+        plot_line = plot_line.fill(fill);
+    }
+    plot_ui.line(plot_line);
+}
@@ -1,7 +1,11 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
 
+pub mod graph;
 pub mod interaction;
+pub mod navigation;
+pub mod network_conditions;
+pub mod network_stats;
 pub mod player_position;
 pub mod spawn;
 pub mod time;
@@ -13,6 +17,9 @@ impl Plugin for DeveloperToolsPlugin {
         // Plugins
         app.add_plugins(bevy_dev::DevPlugins)
             .add_plugins(interaction::InteractionPlugin)
+            .add_plugins(navigation::NavigationPlugin)
+            .add_plugins(network_conditions::NetworkConditionsPlugin)
+            .add_plugins(network_stats::NetworkStatsPlugin)
             .add_plugins(player_position::PlayerPositionPlugin)
             .add_plugins(time::TimePlugin)
             .add_plugins(spawn::SpawnPlugin);
@@ -33,6 +40,9 @@ pub struct DeveloperTools {
     pub hub: bool,
 
     pub interaction: bool,
+    pub navigation: bool,
+    pub network: bool,
+    pub network_conditions: bool,
     pub player_position: bool,
     pub spawn: bool,
     pub time: bool,
@@ -55,6 +65,9 @@ fn hub_ui(mut tools: ResMut<DeveloperTools>, mut ctx: EguiContexts) {
         ui.horizontal_wrapped(|ui| {
             // Please keep these sorted alphabetically!
             ui.toggle_value(&mut tools.interaction, "Interaction");
+            ui.toggle_value(&mut tools.navigation, "Navigation");
+            ui.toggle_value(&mut tools.network, "Network");
+            ui.toggle_value(&mut tools.network_conditions, "Network conditions");
             ui.toggle_value(&mut tools.player_position, "Player position");
             ui.toggle_value(&mut tools.spawn, "Spawn");
             ui.toggle_value(&mut tools.time, "Time");
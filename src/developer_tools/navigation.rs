@@ -0,0 +1,37 @@
+use bevy::prelude::*;
+
+use crate::navigation::{NavMesh, NavigationPath};
+
+use super::tool_enabled;
+
+/// Lifts paths above the navmesh, so they are not hidden by its edges.
+const PATH_OFFSET: Vec3 = Vec3::new(0.0, 0.1, 0.0);
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            debug_lines.run_if(tool_enabled(|tools| tools.navigation)),
+        );
+    }
+}
+
+/// Draws the navmesh, and paths of characters simulated on this machine.
+fn debug_lines(
+    mut gizmos: Gizmos,
+    navmesh: Res<NavMesh>,
+    paths: Query<(&Transform, &NavigationPath)>,
+) {
+    for (from, to) in navmesh.edges() {
+        gizmos.line(from, to, Color::rgba(0.2, 0.6, 1.0, 0.4));
+    }
+
+    for (transform, path) in &paths {
+        let points = std::iter::once(transform.translation)
+            .chain(path.waypoints())
+            .map(|x| x + PATH_OFFSET);
+        gizmos.linestrip(points, Color::YELLOW);
+    }
+}
@@ -0,0 +1,47 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Slider},
+    EguiContexts,
+};
+
+use crate::network::conditioner::{ConditionerRelay, SimulatedConditions};
+
+use super::tool_enabled;
+
+pub struct NetworkConditionsPlugin;
+
+impl Plugin for NetworkConditionsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            ui.run_if(tool_enabled(|tools| tools.network_conditions)),
+        );
+    }
+}
+
+fn ui(
+    mut ctx: EguiContexts,
+    conditions: Res<SimulatedConditions>,
+    relay: Option<Res<ConditionerRelay>>,
+) {
+    let mut conditions = conditions.0.lock().unwrap();
+
+    egui::Window::new("Network conditions").show(ctx.ctx_mut(), |ui| {
+        ui.checkbox(&mut conditions.enabled, "Simulate in new sessions");
+        match &relay {
+            Some(relay) => ui.label(format!("Relaying through {}", relay.local_address())),
+            None => ui.label("Current session is not simulated"),
+        };
+
+        ui.add(Slider::new(&mut conditions.latency, 0.0..=1000.0).text("Latency (ms)"));
+        ui.add(Slider::new(&mut conditions.jitter, 0.0..=500.0).text("Jitter (ms)"));
+
+        let mut loss = conditions.loss * 100.0;
+        ui.add(Slider::new(&mut loss, 0.0..=100.0).text("Packet loss (%)"));
+        conditions.loss = loss / 100.0;
+
+        let mut duplication = conditions.duplication * 100.0;
+        ui.add(Slider::new(&mut duplication, 0.0..=100.0).text("Duplication (%)"));
+        conditions.duplication = duplication / 100.0;
+    });
+}
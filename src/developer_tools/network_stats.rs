@@ -0,0 +1,414 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use bevy::{prelude::*, time::Real, utils::HashMap};
+use bevy_egui::{
+    egui::{self, Color32},
+    EguiContexts,
+};
+use bevy_replicon::{
+    client::ClientSet,
+    prelude::*,
+    renet::{ChannelConfig, NetworkInfo},
+    replicon_core::{replication_rules::Replication, ReplicationChannel},
+    server::ServerSet,
+    RenetSend,
+};
+
+use crate::network::{
+    client::ClientId,
+    protocol::{self, CONTROL_CHANNEL_ID},
+};
+
+use super::{
+    graph::{write_graph, GraphLine},
+    tool_enabled,
+};
+
+/// Number of frames shown in graphs.
+const HISTORY_LENGTH: usize = 120;
+/// Seconds over which bytes sent on channels are summed.
+const TRAFFIC_WINDOW: f32 = 1.0;
+
+pub struct NetworkStatsPlugin;
+
+impl Plugin for NetworkStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NetworkStatsGraph>()
+            .init_resource::<BufferedBytes>()
+            .add_systems(
+                Update,
+                (update_stats, ui)
+                    .chain()
+                    .run_if(tool_enabled(|tools| tools.network)),
+            )
+            // Replicon sends all of its messages between these systems, before renet packs
+            // them into packets, so growth of send buffers is the size of sent messages.
+            .add_systems(
+                PostUpdate,
+                (
+                    record_buffered_bytes
+                        .before(ServerSet::Send)
+                        .before(ClientSet::Send),
+                    measure_sent_bytes
+                        .after(ServerSet::Send)
+                        .after(ClientSet::Send)
+                        .before(RenetSend),
+                )
+                    .run_if(tool_enabled(|tools| tools.network)),
+            );
+    }
+}
+
+#[derive(Debug, Clone, Resource)]
+struct NetworkStatsGraph {
+    /// Connection of this client to the server.
+    server: Option<ConnectionGraph>,
+    /// Connections of clients, when this machine is the server.
+    clients: HashMap<ClientId, ConnectionGraph>,
+    replicated_entities: VecDeque<f64>,
+}
+
+impl Default for NetworkStatsGraph {
+    fn default() -> Self {
+        Self {
+            server: None,
+            clients: default(),
+            replicated_entities: vec![0.0; HISTORY_LENGTH].into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ConnectionGraph {
+    rtt: VecDeque<f64>,
+    packet_loss: VecDeque<f64>,
+    sent: VecDeque<f64>,
+    received: VecDeque<f64>,
+    /// Sent traffic by channel id.
+    channels: BTreeMap<u8, ChannelTraffic>,
+    /// Bytes of replication messages sent to the client in every tick, only on the server.
+    replication: VecDeque<f64>,
+}
+
+impl Default for ConnectionGraph {
+    fn default() -> Self {
+        Self {
+            rtt: vec![0.0; HISTORY_LENGTH].into(),
+            packet_loss: vec![0.0; HISTORY_LENGTH].into(),
+            sent: vec![0.0; HISTORY_LENGTH].into(),
+            received: vec![0.0; HISTORY_LENGTH].into(),
+            channels: default(),
+            replication: vec![0.0; HISTORY_LENGTH].into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct ChannelTraffic {
+    /// Time and size of messages sent in the last [`TRAFFIC_WINDOW`].
+    recent: VecDeque<(f32, usize)>,
+    /// KiB sent per second.
+    per_second: VecDeque<f64>,
+}
+
+impl Default for ChannelTraffic {
+    fn default() -> Self {
+        Self {
+            recent: default(),
+            per_second: vec![0.0; HISTORY_LENGTH].into(),
+        }
+    }
+}
+
+impl ChannelTraffic {
+    fn record(&mut self, now: f32, bytes: usize) {
+        if bytes > 0 {
+            self.recent.push_back((now, bytes));
+        }
+    }
+
+    fn update(&mut self, now: f32) {
+        while let Some((time, _)) = self.recent.front() {
+            if now - time <= TRAFFIC_WINDOW {
+                break;
+            }
+            self.recent.pop_front();
+        }
+
+        let bytes: usize = self.recent.iter().map(|(_, bytes)| bytes).sum();
+        push(
+            &mut self.per_second,
+            bytes as f64 / TRAFFIC_WINDOW as f64 / 1024.0,
+        );
+    }
+}
+
+/// Bytes held in send buffers before replicon sent messages of this frame, by connection and
+/// channel. Connection is `None` for the connection of this client to the server.
+#[derive(Debug, Default, Resource)]
+struct BufferedBytes(HashMap<(Option<ClientId>, u8), usize>);
+
+impl ConnectionGraph {
+    fn update(&mut self, info: NetworkInfo, channels: &[ChannelConfig], now: f32) {
+        push(&mut self.rtt, info.rtt * 1000.0);
+        push(&mut self.packet_loss, info.packet_loss * 100.0);
+        push(&mut self.sent, info.bytes_sent_per_second / 1024.0);
+        push(&mut self.received, info.bytes_received_per_second / 1024.0);
+
+        for config in channels {
+            self.channels
+                .entry(config.channel_id)
+                .or_default()
+                .update(now);
+        }
+    }
+}
+
+fn push(data: &mut VecDeque<f64>, value: f64) {
+    data.pop_front();
+    data.push_back(value);
+}
+
+fn update_stats(
+    mut graph: ResMut<NetworkStatsGraph>,
+    network_channels: Res<NetworkChannels>,
+    client: Option<Res<RenetClient>>,
+    server: Option<Res<RenetServer>>,
+    replicated: Query<(), With<Replication>>,
+    time: Res<Time<Real>>,
+) {
+    let config = protocol::connection_config(&network_channels);
+    let now = time.elapsed_seconds();
+    push(
+        &mut graph.replicated_entities,
+        replicated.iter().count() as f64,
+    );
+
+    match client {
+        Some(client) if client.is_connected() => {
+            graph.server.get_or_insert_with(default).update(
+                client.network_info(),
+                &config.client_channels_config,
+                now,
+            );
+        }
+        _ => graph.server = None,
+    }
+
+    match server {
+        Some(server) => {
+            let clients_id = server.clients_id();
+            graph
+                .clients
+                .retain(|client_id, _| clients_id.iter().any(|x| *client_id == x));
+            for client_id in clients_id {
+                let Ok(info) = server.network_info(client_id) else {
+                    continue;
+                };
+                graph.clients.entry(client_id.into()).or_default().update(
+                    info,
+                    &config.server_channels_config,
+                    now,
+                );
+            }
+        }
+        None => graph.clients.clear(),
+    }
+}
+
+/// Returns bytes held in send buffers of every connection and channel.
+fn buffered_bytes(
+    network_channels: &NetworkChannels,
+    client: Option<&RenetClient>,
+    server: Option<&RenetServer>,
+) -> HashMap<(Option<ClientId>, u8), usize> {
+    let config = protocol::connection_config(network_channels);
+    let mut buffered = HashMap::new();
+
+    if let Some(client) = client.filter(|x| x.is_connected()) {
+        for channel in &config.client_channels_config {
+            let used = channel
+                .max_memory_usage_bytes
+                .saturating_sub(client.channel_available_memory(channel.channel_id));
+            buffered.insert((None, channel.channel_id), used);
+        }
+    }
+
+    if let Some(server) = server {
+        for client_id in server.clients_id() {
+            for channel in &config.server_channels_config {
+                let used = channel
+                    .max_memory_usage_bytes
+                    .saturating_sub(server.channel_available_memory(client_id, channel.channel_id));
+                buffered.insert((Some(client_id.into()), channel.channel_id), used);
+            }
+        }
+    }
+
+    buffered
+}
+
+fn record_buffered_bytes(
+    mut buffered: ResMut<BufferedBytes>,
+    network_channels: Res<NetworkChannels>,
+    client: Option<Res<RenetClient>>,
+    server: Option<Res<RenetServer>>,
+) {
+    buffered.0 = buffered_bytes(&network_channels, client.as_deref(), server.as_deref());
+}
+
+/// Adds messages queued by replicon in this frame to traffic of their channels. Reliable
+/// buffers shrink only when acknowledgements are received and unreliable ones only when renet
+/// sends packets, so neither happens while replicon sends.
+fn measure_sent_bytes(
+    mut graph: ResMut<NetworkStatsGraph>,
+    buffered: Res<BufferedBytes>,
+    network_channels: Res<NetworkChannels>,
+    client: Option<Res<RenetClient>>,
+    server: Option<Res<RenetServer>>,
+    tick: Res<RepliconTick>,
+    time: Res<Time<Real>>,
+) {
+    let now = time.elapsed_seconds();
+    let replication_sent = server.is_some() && tick.is_changed();
+    let mut replication_bytes = HashMap::<ClientId, usize>::new();
+
+    let current = buffered_bytes(&network_channels, client.as_deref(), server.as_deref());
+    for ((client_id, channel_id), used) in current {
+        let previous = buffered.0.get(&(client_id, channel_id)).copied();
+        let sent = used.saturating_sub(previous.unwrap_or(used));
+        let connection = match client_id {
+            Some(client_id) => graph.clients.get_mut(&client_id),
+            None => graph.server.as_mut(),
+        };
+        let Some(connection) = connection else {
+            continue;
+        };
+
+        connection
+            .channels
+            .entry(channel_id)
+            .or_default()
+            .record(now, sent);
+        if let Some(client_id) = client_id.filter(|_| is_replication_channel(channel_id)) {
+            *replication_bytes.entry(client_id).or_default() += sent;
+        }
+    }
+
+    if replication_sent {
+        for (client_id, bytes) in replication_bytes {
+            if let Some(connection) = graph.clients.get_mut(&client_id) {
+                push(&mut connection.replication, bytes as f64);
+            }
+        }
+    }
+}
+
+fn is_replication_channel(channel_id: u8) -> bool {
+    channel_id == u8::from(ReplicationChannel::Reliable)
+        || channel_id == u8::from(ReplicationChannel::Unreliable)
+}
+
+fn ui(mut ctx: EguiContexts, graph: Res<NetworkStatsGraph>) {
+    egui::Window::new("Network").show(ctx.ctx_mut(), |ui| {
+        write_graph(
+            ui,
+            &[GraphLine::new("Entities", &graph.replicated_entities)],
+            "Replicated entities",
+            "replicated_entities",
+            "",
+            |_| {},
+        );
+
+        if graph.server.is_none() && graph.clients.is_empty() {
+            ui.colored_label(Color32::RED, "No connections");
+            return;
+        }
+
+        if let Some(server) = &graph.server {
+            egui::CollapsingHeader::new("Server")
+                .default_open(true)
+                .show(ui, |ui| write_connection(ui, server, "server", false));
+        }
+
+        let mut clients: Vec<_> = graph.clients.iter().collect();
+        clients.sort_by_key(|(client_id, _)| client_id.raw());
+        for (client_id, connection) in clients {
+            egui::CollapsingHeader::new(format!("Client {client_id}")).show(ui, |ui| {
+                write_connection(ui, connection, &client_id.to_string(), true)
+            });
+        }
+    });
+}
+
+/// Writes graphs of the connection, replication is sent only from the server to clients.
+fn write_connection(ui: &mut egui::Ui, connection: &ConnectionGraph, id: &str, replication: bool) {
+    write_graph(
+        ui,
+        &[GraphLine::new("RTT", &connection.rtt)],
+        "Round trip time",
+        &format!("rtt_{id}"),
+        "ms",
+        |_| {},
+    );
+    write_graph(
+        ui,
+        &[GraphLine::new("Loss", &connection.packet_loss)],
+        "Packet loss",
+        &format!("packet_loss_{id}"),
+        "%",
+        |_| {},
+    );
+    write_graph(
+        ui,
+        &[
+            GraphLine::new("Sent", &connection.sent),
+            GraphLine::new("Received", &connection.received),
+        ],
+        "Bandwidth",
+        &format!("bandwidth_{id}"),
+        "KiB/s",
+        |_| {},
+    );
+
+    let names: Vec<_> = connection
+        .channels
+        .keys()
+        .map(|channel_id| channel_name(*channel_id))
+        .collect();
+    let lines: Vec<_> = names
+        .iter()
+        .zip(connection.channels.values())
+        .map(|(name, traffic)| GraphLine::new(name, &traffic.per_second))
+        .collect();
+    write_graph(
+        ui,
+        &lines,
+        "Sent per channel",
+        &format!("channels_{id}"),
+        "KiB/s",
+        |_| {},
+    );
+
+    if replication {
+        write_graph(
+            ui,
+            &[GraphLine::new("Size", &connection.replication)],
+            "Replication messages per tick",
+            &format!("replication_{id}"),
+            "B",
+            |_| {},
+        );
+    }
+}
+
+fn channel_name(channel_id: u8) -> String {
+    match channel_id {
+        id if id == u8::from(ReplicationChannel::Reliable) => String::from("Replication"),
+        id if id == u8::from(ReplicationChannel::Unreliable) => {
+            String::from("Replication (unreliable)")
+        }
+        CONTROL_CHANNEL_ID => String::from("Control"),
+        id => format!("Events {id}"),
+    }
+}
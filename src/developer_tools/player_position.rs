@@ -6,11 +6,13 @@ use bevy_egui::{
     EguiContexts,
 };
 use bevy_rapier3d::plugin::PhysicsSet;
-use egui_plot::{Legend, Line, Plot, PlotPoints, PlotUi};
 
 use crate::character::player::{LocalPlayer, Player};
 
-use super::tool_enabled;
+use super::{
+    graph::{self, GraphLine},
+    tool_enabled,
+};
 
 pub struct PlayerPositionPlugin;
 
@@ -91,83 +93,61 @@ fn ui(
 
             data.update_body(position.translation);
 
-            ui.collapsing(player, |ui| {
-                write_graph(
-                    ui,
-                    &data.body_delta,
-                    "Body delta position",
-                    "body_delta_position",
-                );
-
-                if let Some(attached_camera) = player.attached_camera {
-                    data.update_camera(cameras.get(attached_camera).unwrap().translation);
+            // Names change in the lobby, so headers are identified by entity.
+            egui::CollapsingHeader::new(player)
+                .id_source(entity)
+                .show(ui, |ui| {
                     write_graph(
                         ui,
-                        &data.camera_delta,
-                        "Camera delta position",
-                        "camera_delta_position",
+                        &data.body_delta,
+                        "Body delta position",
+                        "body_delta_position",
                     );
-                } else {
-                    ui.colored_label(Color32::RED, "No attached camera");
-                }
-            });
+
+                    if let Some(attached_camera) = player.attached_camera {
+                        data.update_camera(cameras.get(attached_camera).unwrap().translation);
+                        write_graph(
+                            ui,
+                            &data.camera_delta,
+                            "Camera delta position",
+                            "camera_delta_position",
+                        );
+                    } else {
+                        ui.colored_label(Color32::RED, "No attached camera");
+                    }
+                });
         }
     });
 }
 
+/// Writes graph of absolute deltas on every axis in millimeters.
 fn write_graph(ui: &mut egui::Ui, data: &VecDeque<Vec3>, heading: &str, id: &str) {
-    ui.collapsing(heading, |ui| {
-        Plot::new(id)
-            .include_x(0.0)
-            .include_x(data.len() as f64)
-            .include_y(0.0)
-            .include_y(144.0)
-            .view_aspect(3.0)
-            .height(128.0)
-            .legend(Legend::default())
-            .show(ui, |plot_ui| {
-                write_line(plot_ui, data, 0, "X", Color32::RED);
-                write_line(plot_ui, data, 1, "Y", Color32::GREEN);
-                write_line(plot_ui, data, 2, "Z", Color32::BLUE);
-            });
-
-        ui.horizontal(|ui| {
-            write_info(ui, data, 0, "X", Color32::RED);
-            write_info(ui, data, 1, "Y", Color32::GREEN);
-            write_info(ui, data, 2, "Z", Color32::BLUE);
-
-            let average =
-                (data.iter().map(|x| x.length_squared()).sum::<f32>() / data.len() as f32).sqrt();
-            ui.label(format!("Average: {:.2} mm", average * 1000.0));
-        });
+    let axes = [0, 1, 2].map(|axis| {
+        data.iter()
+            .map(|x| x[axis].abs() as f64 * 1000.0)
+            .collect::<VecDeque<_>>()
     });
-}
-
-fn write_line(
-    plot_ui: &mut PlotUi,
-    data: &VecDeque<Vec3>,
-    element: usize,
-    text: &str,
-    color: Color32,
-) {
-    let points = PlotPoints::from_parametric_callback(
-        |x| (x, data[x as usize][element].abs() as f64 * 1000.0),
-        0.0..=(data.len() - 1) as f64,
-        data.len(),
-    );
-
-    plot_ui.line(Line::new(points).fill(5.0).name(text).color(color));
-}
-
-fn write_info(
-    ui: &mut egui::Ui,
-    data: &VecDeque<Vec3>,
-    element: usize,
-    text: &str,
-    color: Color32,
-) {
-    ui.colored_label(
-        color,
-        format!("{text}: {:.2} mm", data.back().unwrap()[element] * 1000.0),
+    let average =
+        (data.iter().map(|x| x.length_squared()).sum::<f32>() / data.len().max(1) as f32).sqrt();
+
+    graph::write_graph(
+        ui,
+        &[
+            GraphLine::new("X", &axes[0])
+                .with_color(Color32::RED)
+                .with_fill(5.0),
+            GraphLine::new("Y", &axes[1])
+                .with_color(Color32::GREEN)
+                .with_fill(5.0),
+            GraphLine::new("Z", &axes[2])
+                .with_color(Color32::BLUE)
+                .with_fill(5.0),
+        ],
+        heading,
+        id,
+        "mm",
+        |ui| {
+            ui.label(format!("Average: {:.2} mm", average * 1000.0));
+        },
     );
 }
@@ -1,7 +1,7 @@
-use std::ops::Mul;
+use std::{fmt, ops::Mul};
 
 use bevy::prelude::*;
-use bevy_egui::{egui, EguiContexts};
+use bevy_egui::{egui, EguiContexts, EguiPlugin};
 use bevy_replicon::{
     network_event::{
         client_event::{ClientEventAppExt, FromClient},
@@ -14,10 +14,15 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     character::{
-        enemy::{self, DummyEnemy, Enemy},
+        enemy::{self, ai::EnemyAiBundle, DummyEnemy, Enemy},
         player::LocalPlayer,
     },
-    network::{has_local_player, has_server, replication::transform::SyncedTransform},
+    network::{
+        client::ClientId,
+        has_local_player, has_server,
+        permissions::{AuditLog, AuditOutcome, Role, Roles},
+        replication::transform::SyncedTransform,
+    },
 };
 
 use super::tool_enabled;
@@ -27,23 +32,45 @@ pub struct SpawnPlugin;
 impl Plugin for SpawnPlugin {
     fn build(&self, app: &mut App) {
         app.add_client_event::<CommandEvent>(EventType::Unordered)
-            .add_systems(
-                Update,
-                ui.run_if(has_local_player)
-                    .run_if(tool_enabled(|tools| tools.spawn)),
-            )
             .add_systems(
                 PreUpdate,
                 command_server_handler
                     .after(ServerSet::Receive)
                     .run_if(has_server),
             );
+
+        // Headless server does not have any UI.
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.add_systems(
+                Update,
+                ui.run_if(has_local_player)
+                    .run_if(tool_enabled(|tools| tools.spawn)),
+            );
+        }
     }
 }
 
 #[derive(Clone, Copy, Deserialize, Serialize, Sequence, Debug)]
-enum EnemyKind {
+pub enum EnemyKind {
     Dummy,
+    /// Patrols around the place where it spawned and chases players.
+    Hunter,
+}
+
+impl EnemyKind {
+    /// Finds kind by its name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        enum_iterator::all::<Self>().find(|kind| format!("{kind:?}").eq_ignore_ascii_case(name))
+    }
+}
+
+/// Spawns enemy of the kind on the server.
+pub fn spawn_enemy(commands: &mut Commands, kind: EnemyKind, transform: Transform) {
+    let mut entity_commands = enemy::spawn(commands, Enemy, transform);
+    match kind {
+        EnemyKind::Dummy => entity_commands.insert(DummyEnemy),
+        EnemyKind::Hunter => entity_commands.insert(EnemyAiBundle::new(transform.translation)),
+    };
 }
 
 fn ui(
@@ -73,25 +100,45 @@ enum CommandEvent {
     Enemy((Enemy, EnemyKind, SyncedTransform)),
 }
 
+impl fmt::Display for CommandEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandEvent::Enemy((_, kind, transform)) => {
+                let translation = Transform::from(transform.clone()).translation;
+                write!(f, "spawn {kind:?} at {translation}")
+            }
+        }
+    }
+}
+
 fn command_server_handler(
     mut commands: Commands,
     mut event: EventReader<FromClient<CommandEvent>>,
+    mut audit_log: ResMut<AuditLog>,
+    roles: Res<Roles>,
 ) {
-    for FromClient {
-        client_id: _,
-        event,
-    } in event.read()
-    {
+    for FromClient { client_id, event } in event.read() {
+        let client_id = ClientId::from(*client_id);
+        let role = roles.role(client_id);
+        let allowed = role >= Role::Admin;
+        let outcome = match allowed {
+            true => AuditOutcome::Executed,
+            false => AuditOutcome::Denied,
+        };
+        audit_log.record(
+            &format!("Player {client_id}"),
+            role,
+            &event.to_string(),
+            outcome,
+        );
+        if !allowed {
+            warn!("Player {client_id} is not allowed to run developer commands.");
+            continue;
+        }
+
         match event {
-            CommandEvent::Enemy((enemy, kind, transform)) => {
-                enemy::spawn(
-                    &mut commands,
-                    enemy.clone(),
-                    Transform::from(transform.clone()),
-                )
-                .insert(match kind {
-                    EnemyKind::Dummy => DummyEnemy,
-                });
+            CommandEvent::Enemy((_, kind, transform)) => {
+                spawn_enemy(&mut commands, *kind, Transform::from(transform.clone()));
             }
         }
     }
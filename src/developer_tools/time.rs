@@ -7,6 +7,8 @@ use bevy_egui::{
 };
 use egui_plot::{Legend, Line, Plot, PlotPoints, PlotUi};
 
+use crate::network::client::Client;
+
 use super::tool_enabled;
 
 pub struct TimePlugin;
@@ -68,6 +70,7 @@ fn update_reached(mut time_graph: ResMut<TimeGraph>) {
 fn ui(
     mut ctx: EguiContexts,
     mut fixed_time: ResMut<Time<Fixed>>,
+    client: Option<Res<Client>>,
     real_time: Res<Time<bevy::time::Real>>,
     mut time_graph: ResMut<TimeGraph>,
 ) {
@@ -80,9 +83,13 @@ fn ui(
         ui.horizontal(|ui| {
             let mut timestep_hz = 1.0 / fixed_time.timestep().as_secs_f64();
             ui.label("Timestep");
-            ui.add(Slider::new(&mut timestep_hz, 8.0..=260.0));
+            // Clients use the timestep of the server.
+            let slider =
+                ui.add_enabled(client.is_none(), Slider::new(&mut timestep_hz, 8.0..=260.0));
             ui.end_row();
-            fixed_time.set_timestep_hz(timestep_hz);
+            if slider.changed() {
+                fixed_time.set_timestep_hz(timestep_hz);
+            }
         });
 
         ui.horizontal(|ui| {
@@ -0,0 +1,261 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Color32},
+    EguiContexts, EguiPlugin,
+};
+use bevy_replicon::{
+    network_event::{
+        client_event::{ClientEventAppExt, FromClient},
+        EventType,
+    },
+    replicon_core::replication_rules::{AppReplicationExt, Replication},
+    server::ServerSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::player::{
+        registry::{PlayerRegistry, UpdatePlayerRegistry},
+        LocalPlayer, Player, PLAYER_COLORS,
+    },
+    network::{has_local_player, has_server, protocol::ProtocolAppExt, server::Server},
+};
+
+/// Maximum number of characters in player name.
+pub const MAX_NAME_LENGTH: usize = 16;
+
+pub struct LobbyPlugin;
+
+impl Plugin for LobbyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<MatchState>()
+            .replicate::<MatchInfo>()
+            .add_protocol_type::<MatchInfo>()
+            .add_client_event::<LobbyClientEvent>(EventType::Ordered)
+            .add_systems(
+                PreUpdate,
+                (
+                    spawn_match_info.run_if(resource_added::<Server>()),
+                    lobby_server_handler
+                        .after(ServerSet::Receive)
+                        .after(UpdatePlayerRegistry)
+                        .run_if(has_server),
+                ),
+            )
+            .add_systems(Update, sync_match_state);
+
+        // Headless server does not have any UI, the match starts once everybody is ready.
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.init_resource::<LobbyUiState>().add_systems(
+                Update,
+                (
+                    send_preferences,
+                    ui.run_if(has_local_player)
+                        .run_if(in_state(MatchState::Lobby)),
+                ),
+            );
+        } else {
+            app.add_systems(Update, auto_start.run_if(has_server));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, States)]
+pub enum MatchState {
+    /// Players choose names and colors and get ready, they are not able to move.
+    #[default]
+    Lobby,
+    Playing,
+}
+
+/// Replicated state of the match, single entity spawned by the server.
+#[derive(Debug, Clone, Default, Component, Serialize, Deserialize)]
+pub struct MatchInfo {
+    pub started: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
+enum LobbyClientEvent {
+    Name(String),
+    Color(u8),
+    Ready(bool),
+}
+
+/// Preferences of the local player, kept between sessions.
+#[derive(Debug, Default, Resource)]
+struct LobbyUiState {
+    name: String,
+    /// Color chosen by the player, `None` keeps the one assigned by the server.
+    color: Option<u8>,
+}
+
+/// Returns the first color not used by any of the players.
+pub fn next_color<'a>(players: impl Iterator<Item = &'a Player>) -> u8 {
+    let mut used = [false; PLAYER_COLORS.len()];
+    for player in players {
+        used[player.color as usize % PLAYER_COLORS.len()] = true;
+    }
+    used.iter().position(|x| !x).unwrap_or(0) as u8
+}
+
+/// Trims the name and removes characters which do not belong to it.
+fn sanitize_name(name: &str) -> String {
+    name.trim()
+        .chars()
+        .filter(|x| !x.is_control())
+        .take(MAX_NAME_LENGTH)
+        .collect()
+}
+
+fn spawn_match_info(mut commands: Commands) {
+    commands.spawn((MatchInfo::default(), Replication));
+}
+
+fn sync_match_state(
+    query: Query<&MatchInfo, Changed<MatchInfo>>,
+    mut removed: RemovedComponents<MatchInfo>,
+    state: Res<State<MatchState>>,
+    mut next_state: ResMut<NextState<MatchState>>,
+) {
+    let target = match (query.iter().last(), removed.read().last()) {
+        (Some(info), _) if info.started => MatchState::Playing,
+        (Some(_), _) | (None, Some(_)) => MatchState::Lobby,
+        (None, None) => return,
+    };
+
+    if *state.get() != target {
+        info!("Match state changed to {target:?}");
+        next_state.set(target);
+    }
+}
+
+fn lobby_server_handler(
+    mut event: EventReader<FromClient<LobbyClientEvent>>,
+    mut players: Query<&mut Player>,
+    match_info: Query<&MatchInfo>,
+    registry: Res<PlayerRegistry>,
+) {
+    let started = match_info.iter().any(|x| x.started);
+
+    for FromClient { client_id, event } in event.read() {
+        let Some(mut player) = registry
+            .entity((*client_id).into())
+            .and_then(|entity| players.get_mut(entity).ok())
+        else {
+            continue;
+        };
+
+        match event {
+            LobbyClientEvent::Name(name) => player.name = sanitize_name(name),
+            LobbyClientEvent::Color(color) => {
+                if (*color as usize) < PLAYER_COLORS.len() {
+                    player.color = *color;
+                }
+            }
+            LobbyClientEvent::Ready(ready) => {
+                if !started {
+                    player.ready = *ready;
+                }
+            }
+        }
+    }
+}
+
+/// Starts the match of headless server once all players are ready, and returns to the lobby when
+/// all of them leave.
+fn auto_start(mut match_info: Query<&mut MatchInfo>, players: Query<&Player>) {
+    let Ok(mut match_info) = match_info.get_single_mut() else {
+        return;
+    };
+
+    if !match_info.started && !players.is_empty() && players.iter().all(|x| x.ready) {
+        match_info.started = true;
+    } else if match_info.started && players.is_empty() {
+        match_info.started = false;
+    }
+}
+
+/// Sends preferences of the local player once it joins, so they survive between sessions.
+fn send_preferences(
+    mut event: EventWriter<LobbyClientEvent>,
+    state: Res<LobbyUiState>,
+    query: Query<(), Added<LocalPlayer>>,
+) {
+    if query.is_empty() {
+        return;
+    }
+
+    if !state.name.is_empty() {
+        event.send(LobbyClientEvent::Name(state.name.clone()));
+    }
+    if let Some(color) = state.color {
+        event.send(LobbyClientEvent::Color(color));
+    }
+}
+
+fn ui(
+    mut ctx: EguiContexts,
+    mut event: EventWriter<LobbyClientEvent>,
+    mut state: ResMut<LobbyUiState>,
+    mut match_info: Query<&mut MatchInfo>,
+    players: Query<(&Player, Option<&LocalPlayer>)>,
+    server: Option<Res<Server>>,
+) {
+    let local_player = players
+        .iter()
+        .find_map(|(player, local_player)| local_player.map(|_| player));
+    let local_color = local_player.map_or(0, |x| x.color);
+    let mut local_ready = local_player.map_or(false, |x| x.ready);
+
+    egui::Window::new("Lobby").show(ctx.ctx_mut(), |ui| {
+        ui.horizontal(|ui| {
+            ui.label("Name");
+            let response =
+                ui.add(egui::TextEdit::singleline(&mut state.name).char_limit(MAX_NAME_LENGTH));
+            if response.changed() {
+                event.send(LobbyClientEvent::Name(state.name.clone()));
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            for (index, color) in PLAYER_COLORS.iter().enumerate() {
+                let [r, g, b, _] = color.as_rgba_u8();
+                let button = egui::Button::new("    ")
+                    .fill(Color32::from_rgb(r, g, b))
+                    .selected(local_color as usize == index);
+                if ui.add(button).clicked() {
+                    state.color = Some(index as u8);
+                    event.send(LobbyClientEvent::Color(index as u8));
+                }
+            }
+        });
+
+        ui.separator();
+        for (player, _) in &players {
+            let [r, g, b, _] = player.color().as_rgba_u8();
+            ui.horizontal(|ui| {
+                ui.colored_label(Color32::from_rgb(r, g, b), player.to_string());
+                match player.ready {
+                    true => ui.colored_label(Color32::GREEN, "Ready"),
+                    false => ui.label("Not ready"),
+                };
+            });
+        }
+        ui.separator();
+
+        if ui.checkbox(&mut local_ready, "Ready").changed() {
+            event.send(LobbyClientEvent::Ready(local_ready));
+        }
+
+        if server.is_some() {
+            let all_ready = players.iter().all(|(player, _)| player.ready);
+            let start = ui.add_enabled(all_ready, egui::Button::new("Start match"));
+            if start.clicked() {
+                for mut match_info in &mut match_info {
+                    match_info.started = true;
+                }
+            }
+        }
+    });
+}
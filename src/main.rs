@@ -1,70 +1,176 @@
-use std::f32::consts::PI;
+use std::{f32::consts::PI, process::ExitCode, time::Duration};
 
-use bevy::{pbr::CascadeShadowConfigBuilder, prelude::*};
+use bevy::{
+    app::{AppExit, ScheduleRunnerPlugin},
+    log::LogPlugin,
+    pbr::CascadeShadowConfigBuilder,
+    prelude::*,
+    scene::ScenePlugin,
+};
 use bevy_dev::prototype_material::PrototypeMaterialMeshBundle;
 use bevy_rapier3d::prelude::*;
+use bevy_replicon::replicon_core::NetworkChannels;
+use clap::Parser;
+use network::{
+    authentication::Authentication, bans::BanList, conditioner::SimulatedConditions,
+    permissions::AuditLog,
+};
 
 pub mod camera;
 pub mod character;
+pub mod chat;
+pub mod cli;
+pub mod console;
 pub mod developer_tools;
+pub mod lobby;
 pub mod math;
+pub mod navigation;
 pub mod network;
+pub mod projectile;
 
 const TIMESTEP: f64 = 1.0 / 60.0;
 
+/// Fixed boxes which make up the level, as half extents, position and prototype material name.
+const LEVEL: [(Vec3, Vec3, &str); 2] = [
+    (Vec3::new(25.0, 1.0, 25.0), Vec3::ZERO, "floor"),
+    (Vec3::new(2.0, 1.0, 0.5), Vec3::new(0.0, 1.0, 5.0), "wall"),
+];
+
 #[bevy_main]
-fn main() {
-    App::new()
-        .insert_resource(Time::<Fixed>::from_seconds(TIMESTEP))
-        .add_plugins(
-            DefaultPlugins
-                .set(WindowPlugin {
-                    primary_window: Some(Window {
-                        title: "GLASS TRANSITION".into(),
-                        ..default()
-                    }),
-                    ..default()
-                })
-                .set(AssetPlugin {
-                    watch_for_changes_override: Some(true),
+fn main() -> ExitCode {
+    let args = cli::Args::parse();
+
+    if let Some(command) = &args.command {
+        return match command.run() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let authentication = match args.authentication() {
+        Ok(authentication) => authentication,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let ban_list = match BanList::load(&args.bans) {
+        Ok(ban_list) => ban_list,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut app = App::new();
+    app.insert_resource(Time::<Fixed>::from_seconds(TIMESTEP))
+        .insert_resource(args.roles(&authentication.config))
+        .insert_resource(AuditLog::new(args.audit_log.clone()))
+        .insert_resource(SimulatedConditions::new(args.link_conditions()))
+        .insert_resource(authentication)
+        .insert_resource(ban_list);
+
+    match args.headless {
+        true => build_headless(&mut app, &args),
+        false => build_client(&mut app, &args),
+    }
+
+    app.insert_resource(args).run();
+    ExitCode::SUCCESS
+}
+
+fn build_client(app: &mut App, args: &cli::Args) {
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                primary_window: Some(Window {
+                    title: "GLASS TRANSITION".into(),
                     ..default()
                 }),
-        )
-        .add_plugins(bevy_egui::EguiPlugin)
-        .add_plugins(RapierPhysicsPlugin::<()>::default().with_physics_scale(1.0))
-        .add_plugins(RapierDebugRenderPlugin {
-            enabled: false,
-            ..default()
-        })
-        .add_plugins(network::NetworkPlugin)
-        .add_plugins(camera::CameraPlugin)
-        .add_plugins(character::CharacterPlugin)
-        .add_plugins(developer_tools::DeveloperToolsPlugin)
-        .add_systems(Startup, setup)
-        .run();
+                ..default()
+            })
+            .set(AssetPlugin {
+                watch_for_changes_override: Some(true),
+                ..default()
+            }),
+    )
+    .add_plugins(bevy_egui::EguiPlugin)
+    .add_plugins(RapierPhysicsPlugin::<()>::default().with_physics_scale(1.0))
+    .add_plugins(RapierDebugRenderPlugin {
+        enabled: false,
+        ..default()
+    })
+    .add_plugins(network::NetworkPlugin {
+        tick_rate: args.tick_rate,
+        reconnect_window: args.reconnect_window,
+    })
+    .add_plugins(camera::CameraPlugin)
+    .add_plugins(character::CharacterPlugin)
+    .add_plugins(navigation::NavigationPlugin)
+    .add_plugins(projectile::ProjectilePlugin)
+    .add_plugins(lobby::LobbyPlugin)
+    .add_plugins(chat::ChatPlugin)
+    .add_plugins(console::ConsolePlugin { stdin: false })
+    .add_plugins(developer_tools::DeveloperToolsPlugin)
+    .add_systems(Startup, setup);
+}
+
+fn build_headless(app: &mut App, args: &cli::Args) {
+    app.add_plugins(
+        MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(
+            TIMESTEP,
+        ))),
+    )
+    .add_plugins((
+        LogPlugin::default(),
+        TransformPlugin,
+        HierarchyPlugin,
+        AssetPlugin::default(),
+        ScenePlugin,
+    ))
+    // Characters still own mesh and material handles, even if nothing renders them.
+    .init_asset::<Mesh>()
+    .init_asset::<StandardMaterial>()
+    .add_plugins(RapierPhysicsPlugin::<()>::default().with_physics_scale(1.0))
+    .add_plugins(network::NetworkPlugin {
+        tick_rate: args.tick_rate,
+        reconnect_window: args.reconnect_window,
+    })
+    .add_plugins(character::CharacterPlugin)
+    .add_plugins(navigation::NavigationPlugin)
+    .add_plugins(projectile::ProjectilePlugin)
+    .add_plugins(lobby::LobbyPlugin)
+    .add_plugins(chat::ChatPlugin)
+    .add_plugins(console::ConsolePlugin { stdin: true })
+    // Registers network events of developer commands, which must match with clients.
+    .add_plugins(developer_tools::spawn::SpawnPlugin)
+    .add_systems(Startup, (setup_headless, start_headless_server));
 }
 
 fn setup(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
-    commands.spawn((
-        RigidBody::Fixed,
-        Collider::cuboid(25.0, 1.0, 25.0),
-        PrototypeMaterialMeshBundle {
-            mesh: meshes.add(shape::Box::new(50.0, 2.0, 50.0).into()),
-            material: "floor",
-            ..default()
-        },
-    ));
-
-    commands.spawn((
-        RigidBody::Fixed,
-        Collider::cuboid(2.0, 1.0, 0.5),
-        PrototypeMaterialMeshBundle {
-            transform: Transform::from_xyz(0.0, 1.0, 5.0),
-            mesh: meshes.add(shape::Box::new(4.0, 2.0, 1.0).into()),
-            material: "wall",
-            ..default()
-        },
-    ));
+    for (half_extents, position, material) in LEVEL {
+        commands.spawn((
+            RigidBody::Fixed,
+            Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+            PrototypeMaterialMeshBundle {
+                transform: Transform::from_translation(position),
+                mesh: meshes.add(
+                    shape::Box::new(
+                        half_extents.x * 2.0,
+                        half_extents.y * 2.0,
+                        half_extents.z * 2.0,
+                    )
+                    .into(),
+                ),
+                material,
+                ..default()
+            },
+        ));
+    }
 
     commands.spawn(DirectionalLightBundle {
         directional_light: DirectionalLight {
@@ -87,3 +193,34 @@ fn setup(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
         ..default()
     });
 }
+
+fn setup_headless(mut commands: Commands) {
+    for (half_extents, position, _) in LEVEL {
+        commands.spawn((
+            RigidBody::Fixed,
+            Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+            TransformBundle::from_transform(Transform::from_translation(position)),
+        ));
+    }
+}
+
+fn start_headless_server(
+    mut commands: Commands,
+    mut exit: EventWriter<AppExit>,
+    network_channels: Res<NetworkChannels>,
+    authentication: Res<Authentication>,
+    conditions: Res<SimulatedConditions>,
+    args: Res<cli::Args>,
+) {
+    if let Err(err) = network::server::start_listening(
+        &mut commands,
+        &network_channels,
+        &authentication,
+        &conditions,
+        args.port,
+        args.max_clients(&authentication.config),
+    ) {
+        error!("Unable to start headless server: {err}");
+        exit.send(AppExit);
+    }
+}
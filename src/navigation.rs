@@ -0,0 +1,567 @@
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, VecDeque},
+};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::character::player;
+
+/// Lowest vertical component of ground normal on which characters walk.
+const MIN_GROUND_NORMAL: f32 = 0.7;
+/// Gap between ground and the character shape when checking clearance of a cell.
+const GROUND_CLEARANCE: f32 = 0.05;
+/// Distance in cells searched for walkable cell near a point which is not walkable.
+const SNAP_CELLS: i32 = 3;
+/// Distance by which goal moves before its path is planned again.
+const REPLAN_DISTANCE: f32 = 1.0;
+/// Horizontal distance at which waypoint counts as reached.
+const WAYPOINT_TOLERANCE: f32 = 0.3;
+/// Difference of crossed cell borders, in parts of the line, below which it passes a corner.
+const CORNER_EPSILON: f32 = 0.0001;
+
+/// Offsets of neighboring cells, orthogonal first.
+const NEIGHBORS: [IVec2; 8] = [
+    IVec2::new(1, 0),
+    IVec2::new(-1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<NavigationSettings>()
+            .init_resource::<NavMesh>()
+            // Colliders reach Rapier in `PhysicsSet::SyncBackend`, the navmesh is baked after it.
+            .add_systems(PostUpdate, update_navmesh.after(PhysicsSet::Writeback));
+    }
+}
+
+#[derive(Debug, Clone, Resource)]
+pub struct NavigationSettings {
+    /// Size of navmesh cells, smaller cells follow geometry closer but take longer to bake.
+    pub cell_size: f32,
+    /// Highest difference of ground height between neighboring cells which characters climb.
+    pub max_step: f32,
+}
+
+impl Default for NavigationSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 0.5,
+            max_step: 0.3,
+        }
+    }
+}
+
+/// Grid of cells walkable by characters of player size, baked from fixed colliders.
+///
+/// Every cell knows only the topmost ground, so floors above each other are not supported.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct NavMesh {
+    origin: Vec2,
+    cell_size: f32,
+    max_step: f32,
+    size: IVec2,
+    /// Ground height of every cell, `None` when the cell is not walkable.
+    heights: Vec<Option<f32>>,
+    /// Fixed colliders from which the navmesh is baked.
+    sources: HashSet<Entity>,
+}
+
+impl NavMesh {
+    /// Bakes cells over all fixed colliders of the context, checking clearance of character
+    /// shape with the radius and half height.
+    pub fn bake(
+        context: &RapierContext,
+        settings: &NavigationSettings,
+        radius: f32,
+        half_height: f32,
+    ) -> Self {
+        let mut navmesh = Self {
+            cell_size: settings.cell_size,
+            max_step: settings.max_step,
+            ..default()
+        };
+        let Some((min, max)) = fixed_bounds(context) else {
+            return navmesh;
+        };
+
+        navmesh.origin = min.xz();
+        navmesh.size = ((max.xz() - min.xz()) / settings.cell_size)
+            .ceil()
+            .as_ivec2()
+            .max(IVec2::ZERO);
+
+        let shape = Collider::cylinder(half_height, radius);
+        let filter = QueryFilter::only_fixed();
+        let ray_length = max.y - min.y + 1.0;
+        for z in 0..navmesh.size.y {
+            for x in 0..navmesh.size.x {
+                let center = navmesh.cell_center(IVec2::new(x, z));
+                let origin = Vec3::new(center.x, max.y + 1.0, center.y);
+                let height = context
+                    .cast_ray_and_get_normal(origin, Vec3::NEG_Y, ray_length, true, filter)
+                    .filter(|(_, hit)| hit.normal.y >= MIN_GROUND_NORMAL)
+                    .map(|(_, hit)| hit.point.y)
+                    .filter(|ground| {
+                        let position =
+                            Vec3::new(center.x, ground + half_height + GROUND_CLEARANCE, center.y);
+                        context
+                            .intersection_with_shape(position, Quat::IDENTITY, &shape, filter)
+                            .is_none()
+                    });
+                navmesh.heights.push(height);
+            }
+        }
+
+        let walkable = navmesh.heights.iter().flatten().count();
+        info!(
+            "Navmesh baked with {walkable} walkable cells of {}",
+            navmesh.heights.len()
+        );
+        navmesh
+    }
+
+    /// Finds path between the points, returns waypoints after the start, smoothed where the
+    /// straight way is walkable.
+    pub fn find_path(&self, start: Vec3, goal: Vec3) -> Option<Vec<Vec3>> {
+        let start_point = start;
+        let start = self.nearest_walkable(start)?;
+        let goal = self.nearest_walkable(goal)?;
+
+        let mut open = BinaryHeap::new();
+        let mut costs = HashMap::default();
+        let mut came_from = HashMap::default();
+        costs.insert(start, 0.0);
+        open.push(OpenCell {
+            cell: start,
+            estimate: self.distance(start, goal),
+        });
+
+        while let Some(OpenCell { cell, .. }) = open.pop() {
+            if cell == goal {
+                let mut cells = vec![goal];
+                while let Some(previous) = came_from.get(cells.last().unwrap()) {
+                    cells.push(*previous);
+                }
+                let mut points: Vec<_> = cells.iter().rev().map(|x| self.position(*x)).collect();
+                // Smoothing starts where the character stands, not in the center of its cell.
+                if self.is_clear(start_point, points[0]) {
+                    points[0] = start_point;
+                }
+                return Some(self.smooth(&points));
+            }
+
+            let cost = costs[&cell];
+            for offset in NEIGHBORS {
+                let neighbor = cell + offset;
+                if !self.is_passable(cell, offset) {
+                    continue;
+                }
+                let next_cost = cost + self.distance(cell, neighbor);
+                if costs.get(&neighbor).map_or(true, |x| next_cost < *x) {
+                    costs.insert(neighbor, next_cost);
+                    came_from.insert(neighbor, cell);
+                    open.push(OpenCell {
+                        cell: neighbor,
+                        estimate: next_cost + self.distance(neighbor, goal),
+                    });
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Whether character walks straight between the points, every cell touched by the line
+    /// between them is walkable.
+    pub fn is_clear(&self, from: Vec3, to: Vec3) -> bool {
+        let from = (from.xz() - self.origin) / self.cell_size;
+        let offset = (to.xz() - self.origin) / self.cell_size - from;
+        let mut cell = from.floor().as_ivec2();
+        let Some(mut height) = self.height(cell) else {
+            return false;
+        };
+
+        // Fractions of the line at which it crosses the next cell border on each axis.
+        let step = IVec2::new(sign(offset.x), sign(offset.y));
+        let delta = offset.abs().recip();
+        let border = |start: f32, cell: i32, step: i32, delta: f32| match step {
+            1 => (cell as f32 + 1.0 - start) * delta,
+            -1 => (start - cell as f32) * delta,
+            _ => f32::INFINITY,
+        };
+        let mut next = Vec2::new(
+            border(from.x, cell.x, step.x, delta.x),
+            border(from.y, cell.y, step.y, delta.y),
+        );
+
+        while next.min_element() < 1.0 {
+            let offset = if (next.x - next.y).abs() < CORNER_EPSILON {
+                // Passing through a corner touches both cells beside it.
+                for side in [IVec2::new(step.x, 0), IVec2::new(0, step.y)] {
+                    if self.step_height(height, cell + side).is_none() {
+                        return false;
+                    }
+                }
+                next += delta;
+                step
+            } else if next.x < next.y {
+                next.x += delta.x;
+                IVec2::new(step.x, 0)
+            } else {
+                next.y += delta.y;
+                IVec2::new(0, step.y)
+            };
+            cell += offset;
+            match self.step_height(height, cell) {
+                Some(next_height) => height = next_height,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Connections between walkable cells, as lines slightly above the ground.
+    pub fn edges(&self) -> impl Iterator<Item = (Vec3, Vec3)> + '_ {
+        (0..self.size.y)
+            .flat_map(move |z| (0..self.size.x).map(move |x| IVec2::new(x, z)))
+            .flat_map(move |cell| {
+                [IVec2::X, IVec2::Y]
+                    .into_iter()
+                    .filter(move |offset| self.is_passable(cell, *offset))
+                    .map(move |offset| (self.position(cell), self.position(cell + offset)))
+            })
+            .map(|(from, to)| {
+                (
+                    from + Vec3::Y * GROUND_CLEARANCE,
+                    to + Vec3::Y * GROUND_CLEARANCE,
+                )
+            })
+    }
+
+    /// Removes waypoints which can be skipped by walking straight.
+    fn smooth(&self, points: &[Vec3]) -> Vec<Vec3> {
+        let Some(last) = points.last() else {
+            return Vec::new();
+        };
+
+        let mut waypoints = Vec::new();
+        let mut anchor = 0;
+        for index in 2..points.len() {
+            if !self.is_clear(points[anchor], points[index]) {
+                anchor = index - 1;
+                waypoints.push(points[anchor]);
+            }
+        }
+        waypoints.push(*last);
+        waypoints
+    }
+
+    /// Height of the cell when character steps on it from the height.
+    fn step_height(&self, height: f32, cell: IVec2) -> Option<f32> {
+        self.height(cell)
+            .filter(|x| (x - height).abs() <= self.max_step)
+    }
+
+    /// Whether character moves from the cell to its neighbor, without cutting corners.
+    fn is_passable(&self, cell: IVec2, offset: IVec2) -> bool {
+        let connected = |from: IVec2, to: IVec2| match (self.height(from), self.height(to)) {
+            (Some(from), Some(to)) => (from - to).abs() <= self.max_step,
+            _ => false,
+        };
+
+        connected(cell, cell + offset)
+            && (offset.x == 0
+                || offset.y == 0
+                || (connected(cell, cell + IVec2::new(offset.x, 0))
+                    && connected(cell, cell + IVec2::new(0, offset.y))))
+    }
+
+    fn nearest_walkable(&self, point: Vec3) -> Option<IVec2> {
+        let cell = self.cell(point.xz());
+        if self.height(cell).is_some() {
+            return Some(cell);
+        }
+
+        (-SNAP_CELLS..=SNAP_CELLS)
+            .flat_map(|z| (-SNAP_CELLS..=SNAP_CELLS).map(move |x| cell + IVec2::new(x, z)))
+            .filter(|x| self.height(*x).is_some())
+            .min_by(|a, b| {
+                let a = self.cell_center(*a).distance_squared(point.xz());
+                let b = self.cell_center(*b).distance_squared(point.xz());
+                a.total_cmp(&b)
+            })
+    }
+
+    fn height(&self, cell: IVec2) -> Option<f32> {
+        if cell.cmplt(IVec2::ZERO).any() || cell.cmpge(self.size).any() {
+            return None;
+        }
+        self.heights[(cell.y * self.size.x + cell.x) as usize]
+    }
+
+    fn cell(&self, point: Vec2) -> IVec2 {
+        ((point - self.origin) / self.cell_size).floor().as_ivec2()
+    }
+
+    fn cell_center(&self, cell: IVec2) -> Vec2 {
+        self.origin + (cell.as_vec2() + 0.5) * self.cell_size
+    }
+
+    /// Position on the ground in the center of walkable cell.
+    fn position(&self, cell: IVec2) -> Vec3 {
+        let center = self.cell_center(cell);
+        Vec3::new(center.x, self.height(cell).unwrap_or_default(), center.y)
+    }
+
+    fn distance(&self, from: IVec2, to: IVec2) -> f32 {
+        (to - from).as_vec2().length() * self.cell_size
+    }
+}
+
+fn sign(value: f32) -> i32 {
+    match value {
+        x if x > 0.0 => 1,
+        x if x < 0.0 => -1,
+        _ => 0,
+    }
+}
+
+/// Cell waiting in the open set of A*, ordered from the lowest estimate.
+struct OpenCell {
+    cell: IVec2,
+    estimate: f32,
+}
+
+impl PartialEq for OpenCell {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenCell {}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+/// Path followed by a character, planned again when its goal moves.
+#[derive(Debug, Clone, Default, Component)]
+pub struct NavigationPath {
+    goal: Option<Vec3>,
+    waypoints: VecDeque<Vec3>,
+}
+
+impl NavigationPath {
+    /// Returns direction in which the character walks towards the goal, straight when nothing
+    /// is in the way or when there is no path.
+    pub fn direction(&mut self, navmesh: &NavMesh, position: Vec3, goal: Vec3) -> Vec3 {
+        if navmesh.is_clear(position, goal) {
+            self.clear();
+            return goal - position;
+        }
+
+        if self
+            .goal
+            .map_or(true, |x| x.distance(goal) > REPLAN_DISTANCE)
+        {
+            self.goal = Some(goal);
+            self.waypoints = navmesh.find_path(position, goal).unwrap_or_default().into();
+        }
+
+        while let Some(next) = self.waypoints.front() {
+            if next.xz().distance(position.xz()) > WAYPOINT_TOLERANCE {
+                break;
+            }
+            self.waypoints.pop_front();
+        }
+
+        match self.waypoints.front() {
+            Some(next) => *next - position,
+            None => goal - position,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.goal = None;
+        self.waypoints.clear();
+    }
+
+    pub fn waypoints(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.waypoints.iter().copied()
+    }
+}
+
+/// Bakes the navmesh again whenever fixed colliders are added or removed.
+fn update_navmesh(
+    mut navmesh: ResMut<NavMesh>,
+    mut removed: RemovedComponents<Collider>,
+    added: Query<(Entity, Option<&RigidBody>), Added<Collider>>,
+    context: Res<RapierContext>,
+    settings: Res<NavigationSettings>,
+) {
+    let mut changed = settings.is_changed();
+    for (entity, rigid_body) in &added {
+        // Colliders without rigid body are fixed.
+        if rigid_body.map_or(true, |x| *x == RigidBody::Fixed) {
+            navmesh.sources.insert(entity);
+            changed = true;
+        }
+    }
+    for entity in removed.read() {
+        changed |= navmesh.sources.remove(&entity);
+    }
+    if !changed {
+        return;
+    }
+
+    let sources = std::mem::take(&mut navmesh.sources);
+    *navmesh = NavMesh {
+        sources,
+        ..NavMesh::bake(&context, &settings, player::RADIUS, player::HALF_HEIGHT)
+    };
+}
+
+/// Bounding box of all fixed colliders.
+fn fixed_bounds(context: &RapierContext) -> Option<(Vec3, Vec3)> {
+    let mut bounds: Option<(Vec3, Vec3)> = None;
+    for (_, collider) in context.colliders.iter() {
+        let fixed = collider.parent().map_or(true, |x| {
+            context.bodies.get(x).map_or(false, |x| x.is_fixed())
+        });
+        if !fixed || collider.is_sensor() {
+            continue;
+        }
+
+        let aabb = collider.compute_aabb();
+        let min = Vec3::new(aabb.mins.x, aabb.mins.y, aabb.mins.z);
+        let max = Vec3::new(aabb.maxs.x, aabb.maxs.y, aabb.maxs.z);
+        bounds = Some(match bounds {
+            Some((x, y)) => (x.min(min), y.max(max)),
+            None => (min, max),
+        });
+    }
+    bounds
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds navmesh from rows of cells, `#` is blocked and digits are ground heights.
+    fn grid(rows: &[&str]) -> NavMesh {
+        let heights = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|x| x.to_digit(10).map(|x| x as f32))
+            .collect();
+        NavMesh {
+            origin: Vec2::ZERO,
+            cell_size: 1.0,
+            max_step: 0.3,
+            size: IVec2::new(rows[0].len() as i32, rows.len() as i32),
+            heights,
+            sources: HashSet::default(),
+        }
+    }
+
+    fn point(x: f32, z: f32) -> Vec3 {
+        Vec3::new(x, 0.0, z)
+    }
+
+    /// Asserts that walking along the path never enters a blocked cell.
+    fn assert_walkable(navmesh: &NavMesh, start: Vec3, path: &[Vec3]) {
+        let mut from = start;
+        for &to in path {
+            let steps = (from.distance(to) / 0.01).ceil() as usize;
+            for step in 0..=steps {
+                let point = from.lerp(to, step as f32 / steps.max(1) as f32);
+                assert!(
+                    navmesh.height(navmesh.cell(point.xz())).is_some(),
+                    "{point} between {from} and {to} is blocked"
+                );
+            }
+            from = to;
+        }
+    }
+
+    #[test]
+    fn start_is_goal() {
+        let navmesh = grid(&["000", "000", "000"]);
+        let path = navmesh.find_path(point(1.5, 1.5), point(1.5, 1.5));
+        assert_eq!(path, Some(vec![point(1.5, 1.5)]));
+    }
+
+    #[test]
+    fn unreachable_goal() {
+        let navmesh = grid(&[
+            "00#00", //
+            "00#00", "00#00",
+        ]);
+        assert_eq!(navmesh.find_path(point(0.5, 0.5), point(4.5, 2.5)), None);
+
+        // Climbing over the step is too high as well.
+        let navmesh = grid(&["00100"]);
+        assert_eq!(navmesh.find_path(point(0.5, 0.5), point(4.5, 0.5)), None);
+    }
+
+    #[test]
+    fn routes_around_blocked_cells() {
+        let navmesh = grid(&[
+            "00000", //
+            "0###0", "0#00#", "0#00#", "00000",
+        ]);
+        let start = point(2.5, 0.5);
+        let goal = point(2.5, 2.5);
+        assert!(!navmesh.is_clear(start, goal));
+
+        let path = navmesh.find_path(start, goal).unwrap();
+        assert_eq!(path.last(), Some(&goal));
+        assert_walkable(&navmesh, start, &path);
+    }
+
+    #[test]
+    fn smoothing_does_not_cut_blocked_cells() {
+        let navmesh = grid(&[
+            "000000", //
+            "000000", "00##00", "00##00", "000000", "000000",
+        ]);
+        for (start, goal) in [
+            (point(0.5, 0.5), point(5.5, 5.5)),
+            (point(5.5, 0.5), point(0.5, 5.5)),
+            (point(2.5, 0.5), point(3.5, 5.5)),
+            (point(0.5, 3.0), point(5.5, 2.0)),
+        ] {
+            let path = navmesh.find_path(start, goal).unwrap();
+            assert!(path.len() > 1, "{start} to {goal} goes straight");
+            assert_walkable(&navmesh, start, &path);
+        }
+    }
+
+    #[test]
+    fn straight_when_clear() {
+        let navmesh = grid(&["000", "000", "000"]);
+        let path = navmesh.find_path(point(0.5, 0.5), point(2.5, 2.5));
+        assert_eq!(path, Some(vec![point(2.5, 2.5)]));
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    fs::File,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use bevy::prelude::*;
+use bevy_replicon::renet::transport::{
+    generate_random_bytes, ClientAuthentication, ConnectToken, ServerAuthentication,
+};
+
+use super::{
+    client::ClientId, config::NetworkConfig, network_error::NetworkError,
+    protocol::NETCODE_PROTOCOL_ID,
+};
+
+/// How connections between clients and the server are authenticated.
+#[derive(Debug, Clone, Resource)]
+pub struct Authentication {
+    /// Skips authentication entirely, only for development.
+    pub insecure: bool,
+    pub config: NetworkConfig,
+    /// Connect token issued for this client.
+    pub connect_token: Option<PathBuf>,
+}
+
+impl Authentication {
+    /// Returns authentication of the server and addresses under which it is reachable.
+    pub fn server(
+        &self,
+        bind_address: SocketAddr,
+    ) -> Result<(ServerAuthentication, Vec<SocketAddr>), NetworkError> {
+        if self.insecure {
+            return Ok((ServerAuthentication::Unsecure, vec![bind_address]));
+        }
+
+        let public_addresses = match self.config.public_addresses.is_empty() {
+            true => vec![bind_address],
+            false => self.config.public_addresses.clone(),
+        };
+        let authentication = ServerAuthentication::Secure {
+            private_key: self.config.private_key()?,
+        };
+        Ok((authentication, public_addresses))
+    }
+
+    pub fn client(
+        &self,
+        server_address: SocketAddr,
+    ) -> Result<(ClientAuthentication, ClientId), NetworkError> {
+        if self.insecure {
+            let client_id = random_client_id();
+            let authentication = ClientAuthentication::Unsecure {
+                client_id,
+                protocol_id: NETCODE_PROTOCOL_ID,
+                server_addr: server_address,
+                user_data: None,
+            };
+            return Ok((authentication, ClientId::from_raw(client_id)));
+        }
+
+        let path = self
+            .connect_token
+            .as_ref()
+            .ok_or(NetworkError::MissingConnectToken)?;
+        let connect_token = read_connect_token(path)?;
+        let client_id = ClientId::from_raw(connect_token.client_id);
+        Ok((ClientAuthentication::Secure { connect_token }, client_id))
+    }
+}
+
+/// Issues connect tokens signed by the private key of the server.
+pub struct TokenIssuer {
+    private_key: [u8; 32],
+    server_addresses: Vec<SocketAddr>,
+    expire_seconds: u64,
+}
+
+impl TokenIssuer {
+    pub fn new(
+        config: &NetworkConfig,
+        server_addresses: Vec<SocketAddr>,
+        expire_seconds: u64,
+    ) -> Result<Self, NetworkError> {
+        let server_addresses = match server_addresses.is_empty() {
+            true => config.public_addresses.clone(),
+            false => server_addresses,
+        };
+        if server_addresses.is_empty() {
+            return Err(NetworkError::MissingAddress);
+        }
+
+        Ok(Self {
+            private_key: config.private_key()?,
+            server_addresses,
+            expire_seconds,
+        })
+    }
+
+    /// Issues token for a new client, with random 64-bit id which is unlikely to collide with
+    /// ids of other tokens.
+    pub fn issue(&self) -> Result<ConnectToken, NetworkError> {
+        let client_id = random_client_id();
+
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        ConnectToken::generate(
+            current_time,
+            NETCODE_PROTOCOL_ID,
+            self.expire_seconds,
+            client_id,
+            15,
+            self.server_addresses.clone(),
+            None,
+            &self.private_key,
+        )
+        .map_err(|_| NetworkError::UnableIssueConnectToken)
+    }
+}
+
+pub fn write_connect_token(path: &Path, connect_token: &ConnectToken) -> Result<(), NetworkError> {
+    let mut file = File::create(path).map_err(|_| NetworkError::UnableWriteConnectToken)?;
+    connect_token
+        .write(&mut file)
+        .map_err(|_| NetworkError::UnableWriteConnectToken)
+}
+
+fn read_connect_token(path: &Path) -> Result<ConnectToken, NetworkError> {
+    let mut file = File::open(path).map_err(|_| NetworkError::InvalidConnectToken)?;
+    ConnectToken::read(&mut file).map_err(|_| NetworkError::InvalidConnectToken)
+}
+
+/// Generates random client id, zero is reserved for the server.
+fn random_client_id() -> u64 {
+    loop {
+        let client_id = u64::from_le_bytes(generate_random_bytes());
+        if client_id != 0 {
+            return client_id;
+        }
+    }
+}
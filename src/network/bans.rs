@@ -0,0 +1,78 @@
+use std::{
+    fs,
+    net::IpAddr,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{client::ClientId, network_error::NetworkError};
+
+/// Players banned from the server, stored as TOML and rewritten after every change.
+#[derive(Debug, Clone, Default, Resource, Serialize, Deserialize)]
+pub struct BanList {
+    #[serde(default)]
+    bans: Vec<Ban>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ban {
+    /// Client id from the connect token.
+    pub client_id: u64,
+    /// Address from which the client connected, catches clients without connect token.
+    pub ip: Option<IpAddr>,
+    pub reason: String,
+}
+
+impl BanList {
+    /// Loads the list from the file, missing file is an empty list which is created on first ban.
+    pub fn load(path: &Path) -> Result<Self, NetworkError> {
+        let mut ban_list = match fs::read_to_string(path) {
+            Ok(content) => toml::from_str(&content).map_err(|_| NetworkError::InvalidBanList)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(_) => return Err(NetworkError::UnableReadBanList),
+        };
+        ban_list.path = Some(path.to_path_buf());
+        Ok(ban_list)
+    }
+
+    /// Returns ban which applies to the client connected from the address.
+    pub fn find(&self, client_id: ClientId, ip: Option<IpAddr>) -> Option<&Ban> {
+        self.bans
+            .iter()
+            .find(|ban| ban.client_id == client_id.raw() || (ban.ip.is_some() && ban.ip == ip))
+    }
+
+    pub fn ban(&mut self, ban: Ban) -> Result<(), NetworkError> {
+        self.bans.push(ban);
+        self.save()
+    }
+
+    /// Removes bans of the client id or address, returns how many were removed.
+    pub fn unban(
+        &mut self,
+        client_id: Option<ClientId>,
+        ip: Option<IpAddr>,
+    ) -> Result<usize, NetworkError> {
+        let count = self.bans.len();
+        self.bans.retain(|ban| {
+            client_id.map_or(true, |x| x.raw() != ban.client_id) && (ip.is_none() || ban.ip != ip)
+        });
+        let removed = count - self.bans.len();
+        if removed > 0 {
+            self.save()?;
+        }
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<(), NetworkError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let content = toml::to_string(self).map_err(|_| NetworkError::UnableWriteBanList)?;
+        fs::write(path, content).map_err(|_| NetworkError::UnableWriteBanList)
+    }
+}
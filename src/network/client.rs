@@ -1,29 +1,41 @@
 use std::{
     fmt,
-    net::{IpAddr, SocketAddr, UdpSocket},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
     time::SystemTime,
 };
 
 use bevy::prelude::*;
 use bevy_replicon::{
     prelude::*,
-    renet::{
-        transport::{ClientAuthentication, NetcodeClientTransport},
-        ConnectionConfig,
-    },
+    renet::transport::{generate_random_bytes, NetcodeClientTransport},
 };
 use serde::{Deserialize, Serialize};
 
-use super::network_error::NetworkError;
+use super::{
+    authentication::Authentication,
+    conditioner::{ConditionerRelay, SimulatedConditions},
+    network_error::NetworkError,
+    protocol::{self, ClientHandshake},
+};
 
 #[derive(Resource)]
 pub struct Client {
     pub id: ClientId,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ClientId(u64);
 
+impl ClientId {
+    pub fn from_raw(value: u64) -> Self {
+        Self(value)
+    }
+
+    pub fn raw(&self) -> u64 {
+        self.0
+    }
+}
+
 impl fmt::Display for ClientId {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "#{}", self.0)
@@ -36,48 +48,125 @@ impl From<bevy_replicon::renet::ClientId> for ClientId {
     }
 }
 
+impl From<ClientId> for bevy_replicon::renet::ClientId {
+    fn from(client_id: ClientId) -> Self {
+        Self::from_raw(client_id.0)
+    }
+}
+
 impl PartialEq<&bevy_replicon::renet::ClientId> for ClientId {
     fn eq(&self, other: &&bevy_replicon::renet::ClientId) -> bool {
         self.0 == other.raw()
     }
 }
 
+/// Session of this client with a server, which survives reconnections.
+#[derive(Debug, Clone, Resource)]
+pub struct ClientSession {
+    pub server_address: SocketAddr,
+    /// Identifies this client to the server across reconnections, so it gets back its player.
+    pub token: u64,
+    /// Whether handshake with the server was completed at least once.
+    pub established: bool,
+    /// Local port, reused by reconnections so the server accepts the same connect token again.
+    local_port: u16,
+}
+
 pub fn start_connection(
-    mut commands: Commands,
-    network_channels: Res<NetworkChannels>,
+    commands: &mut Commands,
+    network_channels: &NetworkChannels,
+    authentication: &Authentication,
+    conditions: &SimulatedConditions,
     server_address: IpAddr,
     server_port: u16,
 ) -> Result<(), NetworkError> {
-    let client = RenetClient::new(ConnectionConfig {
-        server_channels_config: network_channels.get_server_configs(),
-        client_channels_config: network_channels.get_client_configs(),
-        ..Default::default()
-    });
+    let session = ClientSession {
+        server_address: SocketAddr::new(server_address, server_port),
+        token: u64::from_le_bytes(generate_random_bytes()),
+        established: false,
+        local_port: 0,
+    };
+    connect(
+        commands,
+        network_channels,
+        authentication,
+        conditions,
+        session,
+    )
+}
+
+/// Connects again to the server of lost session.
+pub fn reconnect(
+    commands: &mut Commands,
+    network_channels: &NetworkChannels,
+    authentication: &Authentication,
+    conditions: &SimulatedConditions,
+    session: &ClientSession,
+) -> Result<(), NetworkError> {
+    connect(
+        commands,
+        network_channels,
+        authentication,
+        conditions,
+        session.clone(),
+    )
+}
+
+fn connect(
+    commands: &mut Commands,
+    network_channels: &NetworkChannels,
+    authentication: &Authentication,
+    conditions: &SimulatedConditions,
+    mut session: ClientSession,
+) -> Result<(), NetworkError> {
+    let client = RenetClient::new(protocol::connection_config(network_channels));
 
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
-    let client_id = current_time.as_millis() as u64;
-
-    let address = SocketAddr::new(server_address, server_port);
-    let socket =
-        UdpSocket::bind((server_address, 0)).map_err(|_| NetworkError::UnableBindSocket)?;
-    let authentication = ClientAuthentication::Unsecure {
-        client_id,
-        protocol_id: super::PROTOCOL_ID,
-        server_addr: address,
-        user_data: None,
+
+    let mut address = session.server_address;
+    // Relay is kept only once the client starts, dropping it stops its thread.
+    let mut relay = None;
+    // Connect token holds the address of the server, so only insecure connections can be relayed.
+    if conditions.is_enabled() && authentication.insecure {
+        let relay_address = match address {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::LOCALHOST.into(), 0),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 0),
+        };
+        let conditioner = ConditionerRelay::spawn(relay_address, address, conditions)
+            .map_err(|_| NetworkError::UnableBindSocket)?;
+        address = conditioner.local_address();
+        relay = Some(conditioner);
+    } else if conditions.is_enabled() {
+        warn!("Network conditions are simulated on the client only with --insecure.");
+    }
+
+    let local_address: IpAddr = match address {
+        SocketAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        SocketAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
     };
+    let socket = UdpSocket::bind((local_address, session.local_port))
+        .or_else(|_| UdpSocket::bind((local_address, 0)))
+        .map_err(|_| NetworkError::UnableBindSocket)?;
+    session.local_port = socket
+        .local_addr()
+        .map_err(|_| NetworkError::UnableBindSocket)?
+        .port();
+    let (authentication, client_id) = authentication.client(address)?;
     let transport = NetcodeClientTransport::new(current_time, authentication, socket)
         .map_err(|_| NetworkError::UnableCreateClientTransport)?;
 
-    info!("Client started on {}", address);
+    info!("Client started on {}", session.server_address);
 
-    commands.insert_resource(Client {
-        id: ClientId(client_id),
-    });
+    if let Some(relay) = relay {
+        commands.insert_resource(relay);
+    }
+    commands.insert_resource(Client { id: client_id });
     commands.insert_resource(client);
     commands.insert_resource(transport);
+    commands.insert_resource(ClientHandshake::default());
+    commands.insert_resource(session);
 
     Ok(())
 }
@@ -0,0 +1,254 @@
+use std::{
+    io::{self, ErrorKind},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::renet::transport::generate_random_bytes;
+
+/// Time without traffic after which the relay forgets a peer.
+const PEER_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time the relay thread sleeps when there is nothing to do.
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+/// Bad network conditions simulated on every packet, in both directions.
+#[derive(Debug, Clone, Default)]
+pub struct LinkConditions {
+    /// Whether new sessions go through the simulator, changes apply from the next session.
+    pub enabled: bool,
+    /// Milliseconds added to every packet.
+    pub latency: f32,
+    /// Maximum random milliseconds added on top of the latency, reorders packets.
+    pub jitter: f32,
+    /// Chance of dropping a packet, from 0 to 1.
+    pub loss: f32,
+    /// Chance of sending a packet twice, from 0 to 1.
+    pub duplication: f32,
+}
+
+/// Conditions shared with the relay thread, changes apply immediately to running relay.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct SimulatedConditions(pub Arc<Mutex<LinkConditions>>);
+
+impl SimulatedConditions {
+    pub fn new(conditions: LinkConditions) -> Self {
+        Self(Arc::new(Mutex::new(conditions)))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.lock().unwrap().enabled
+    }
+}
+
+/// UDP relay between the transport and the network, which applies simulated conditions.
+///
+/// Every peer gets its own upstream socket, so the target still sees peers at different
+/// addresses. The relay stops when this resource is dropped.
+#[derive(Resource)]
+pub struct ConditionerRelay {
+    local_address: SocketAddr,
+    running: Arc<AtomicBool>,
+}
+
+impl ConditionerRelay {
+    /// Starts relay listening on the address and forwarding to the target.
+    pub fn spawn(
+        listen_address: SocketAddr,
+        target: SocketAddr,
+        conditions: &SimulatedConditions,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(listen_address)?;
+        socket.set_nonblocking(true)?;
+        let local_address = socket.local_addr()?;
+        let running = Arc::new(AtomicBool::new(true));
+
+        let mut relay = RelayThread {
+            socket,
+            target,
+            peers: default(),
+            queue: Vec::new(),
+            conditions: conditions.0.clone(),
+            random: Random::new(),
+        };
+        let thread_running = running.clone();
+        thread::spawn(move || {
+            while thread_running.load(Ordering::Relaxed) {
+                if !relay.update() {
+                    thread::sleep(IDLE_SLEEP);
+                }
+            }
+        });
+
+        info!("Network conditions simulated on {local_address} for {target}");
+        Ok(Self {
+            local_address,
+            running,
+        })
+    }
+
+    pub fn local_address(&self) -> SocketAddr {
+        self.local_address
+    }
+}
+
+impl Drop for ConditionerRelay {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+struct Peer {
+    upstream: UdpSocket,
+    last_seen: Instant,
+}
+
+/// Packet waiting for its simulated delay.
+struct DelayedPacket {
+    due: Instant,
+    /// Peer of the packet, `None` when it is sent to the target.
+    to_peer: Option<SocketAddr>,
+    from_peer: SocketAddr,
+    data: Vec<u8>,
+}
+
+struct RelayThread {
+    socket: UdpSocket,
+    target: SocketAddr,
+    peers: HashMap<SocketAddr, Peer>,
+    queue: Vec<DelayedPacket>,
+    conditions: Arc<Mutex<LinkConditions>>,
+    random: Random,
+}
+
+impl RelayThread {
+    /// Moves packets in both directions, returns whether anything happened.
+    fn update(&mut self) -> bool {
+        let now = Instant::now();
+        let conditions = self.conditions.lock().unwrap().clone();
+        let mut active = false;
+        let mut buffer = [0; 2048];
+
+        while let Some((length, peer)) = receive(&self.socket, &mut buffer) {
+            active = true;
+            if !self.peers.contains_key(&peer) {
+                let Ok(upstream) = bind_upstream(self.target) else {
+                    continue;
+                };
+                self.peers.insert(
+                    peer,
+                    Peer {
+                        upstream,
+                        last_seen: now,
+                    },
+                );
+            }
+            self.peers.get_mut(&peer).unwrap().last_seen = now;
+            self.schedule(&conditions, now, None, peer, &buffer[..length]);
+        }
+
+        let peers: Vec<_> = self.peers.keys().copied().collect();
+        for peer in peers {
+            while let Some((length, _)) = receive(&self.peers[&peer].upstream, &mut buffer) {
+                active = true;
+                self.schedule(&conditions, now, Some(peer), peer, &buffer[..length]);
+            }
+        }
+
+        let mut index = 0;
+        while index < self.queue.len() {
+            if self.queue[index].due > now {
+                index += 1;
+                continue;
+            }
+            active = true;
+            let packet = self.queue.swap_remove(index);
+            let Some(peer) = self.peers.get(&packet.from_peer) else {
+                continue;
+            };
+            let result = match packet.to_peer {
+                Some(to_peer) => self.socket.send_to(&packet.data, to_peer),
+                None => peer.upstream.send_to(&packet.data, self.target),
+            };
+            if let Err(err) = result {
+                debug!("Unable to relay packet: {err}");
+            }
+        }
+
+        self.peers
+            .retain(|_, peer| now.duration_since(peer.last_seen) < PEER_TIMEOUT);
+        active
+    }
+
+    fn schedule(
+        &mut self,
+        conditions: &LinkConditions,
+        now: Instant,
+        to_peer: Option<SocketAddr>,
+        from_peer: SocketAddr,
+        data: &[u8],
+    ) {
+        if self.random.chance(conditions.loss) {
+            return;
+        }
+        let copies = match self.random.chance(conditions.duplication) {
+            true => 2,
+            false => 1,
+        };
+        for _ in 0..copies {
+            let delay = conditions.latency + conditions.jitter * self.random.next_f32();
+            self.queue.push(DelayedPacket {
+                due: now + Duration::from_secs_f32(delay.max(0.0) / 1000.0),
+                to_peer,
+                from_peer,
+                data: data.to_vec(),
+            });
+        }
+    }
+}
+
+fn receive(socket: &UdpSocket, buffer: &mut [u8]) -> Option<(usize, SocketAddr)> {
+    match socket.recv_from(buffer) {
+        Ok(received) => Some(received),
+        Err(err) if err.kind() == ErrorKind::WouldBlock => None,
+        Err(err) => {
+            debug!("Unable to receive packet: {err}");
+            None
+        }
+    }
+}
+
+fn bind_upstream(target: SocketAddr) -> io::Result<UdpSocket> {
+    let local_address = match target {
+        SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+        SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+    };
+    let socket = UdpSocket::bind(local_address)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+/// Xorshift generator, good enough for deciding the fate of packets.
+struct Random(u64);
+
+impl Random {
+    fn new() -> Self {
+        Self(u64::from_le_bytes(generate_random_bytes()) | 1)
+    }
+
+    fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        probability > 0.0 && self.next_f32() < probability
+    }
+}
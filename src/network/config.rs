@@ -0,0 +1,55 @@
+use std::{fs, net::SocketAddr, path::Path};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::network_error::NetworkError;
+
+/// Server configuration file, stored as TOML.
+#[derive(Debug, Clone, Default, Resource, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Key shared by the server and the connect token issuer, as 64 hexadecimal digits.
+    pub private_key: Option<String>,
+    /// Addresses under which clients reach the server, written into issued connect tokens.
+    #[serde(default)]
+    pub public_addresses: Vec<SocketAddr>,
+    /// Maximum number of players, overridden by `--max-clients`.
+    pub max_clients: Option<usize>,
+    /// Name of the server shown to players in the local network.
+    pub name: Option<String>,
+    /// Client ids from connect tokens of players allowed to run developer commands.
+    #[serde(default)]
+    pub admins: Vec<u64>,
+}
+
+impl NetworkConfig {
+    pub fn load(path: &Path) -> Result<Self, NetworkError> {
+        let content = fs::read_to_string(path).map_err(|_| NetworkError::UnableReadConfig)?;
+        toml::from_str(&content).map_err(|_| NetworkError::InvalidConfig)
+    }
+
+    pub fn private_key(&self) -> Result<[u8; 32], NetworkError> {
+        let private_key = self
+            .private_key
+            .as_ref()
+            .ok_or(NetworkError::MissingPrivateKey)?;
+        decode_key(private_key).ok_or(NetworkError::InvalidPrivateKey)
+    }
+}
+
+pub fn encode_key(key: &[u8; 32]) -> String {
+    key.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+fn decode_key(value: &str) -> Option<[u8; 32]> {
+    let value = value.trim();
+    if value.len() != 64 || !value.is_ascii() {
+        return None;
+    }
+
+    let mut key = [0; 32];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(key)
+}
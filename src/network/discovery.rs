@@ -0,0 +1,212 @@
+use std::{
+    io::ErrorKind,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::{bincode, renet::transport::generate_random_bytes};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    authentication::Authentication,
+    has_server,
+    protocol::Protocol,
+    server::{PlayerSessions, Server},
+};
+
+/// First port on which clients listen for beacons of LAN servers.
+pub const DISCOVERY_PORT: u16 = 13100;
+/// Number of consecutive ports tried by clients, so several games on one machine discover servers.
+const DISCOVERY_PORT_COUNT: u16 = 4;
+/// Identifies beacons of this game among other datagrams on the discovery ports.
+const BEACON_MAGIC: u32 = 0x474c_5452;
+/// Seconds between two beacons of the server.
+const BEACON_INTERVAL: f32 = 1.0;
+/// Seconds after the last beacon when the server is removed from the list.
+const SERVER_TIMEOUT: f32 = 3.5;
+/// Name of the server when the network config does not specify one.
+pub const DEFAULT_SERVER_NAME: &str = "Glass Transition server";
+
+pub struct DiscoveryPlugin {
+    /// Whether discovered servers are listed, only hosting is possible without it.
+    pub listen: bool,
+}
+
+impl Plugin for DiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, broadcast_beacon.run_if(has_server));
+
+        if self.listen {
+            app.insert_resource(DiscoveredServers::bind())
+                .add_systems(Update, receive_beacons);
+        }
+    }
+}
+
+/// Periodically sent by hosting server to every machine in the local network.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Beacon {
+    magic: u32,
+    /// Random id of the hosting game, the same server is heard both on broadcast and loopback.
+    instance: u64,
+    pub name: String,
+    pub players: usize,
+    pub max_clients: usize,
+    pub version: String,
+    pub protocol_id: u64,
+    /// Port of the game server, which can differ from the port the beacon is sent from.
+    pub port: u16,
+}
+
+/// Server found by its beacon.
+#[derive(Debug, Clone)]
+pub struct DiscoveredServer {
+    pub address: SocketAddr,
+    pub beacon: Beacon,
+    last_seen: f32,
+}
+
+/// Servers in the local network, by instance id from their beacon.
+#[derive(Resource)]
+pub struct DiscoveredServers {
+    socket: Option<UdpSocket>,
+    servers: HashMap<u64, DiscoveredServer>,
+}
+
+impl DiscoveredServers {
+    fn bind() -> Self {
+        let socket = (DISCOVERY_PORT..DISCOVERY_PORT + DISCOVERY_PORT_COUNT).find_map(|port| {
+            let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)).ok()?;
+            socket.set_nonblocking(true).ok()?;
+            info!("Listening for LAN servers on port {port}");
+            Some(socket)
+        });
+        if socket.is_none() {
+            warn!("Unable to bind any discovery port, LAN servers are not listed.");
+        }
+
+        Self {
+            socket,
+            servers: default(),
+        }
+    }
+
+    /// Discovered servers, sorted by name and address.
+    pub fn iter(&self) -> impl Iterator<Item = &DiscoveredServer> {
+        let mut servers: Vec<_> = self.servers.values().collect();
+        servers.sort_by(|a, b| (&a.beacon.name, a.address).cmp(&(&b.beacon.name, b.address)));
+        servers.into_iter()
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.socket.is_some()
+    }
+}
+
+/// Socket of the server beacon, with time of the next beacon.
+struct BeaconSocket {
+    socket: UdpSocket,
+    instance: u64,
+    next_beacon: f32,
+}
+
+fn broadcast_beacon(
+    mut beacon_socket: Local<Option<BeaconSocket>>,
+    server: Res<Server>,
+    sessions: Res<PlayerSessions>,
+    protocol: Res<Protocol>,
+    authentication: Res<Authentication>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    if beacon_socket.is_none() {
+        let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+            Ok(socket) => socket,
+            Err(err) => {
+                warn!("Unable to bind beacon socket: {err}");
+                return;
+            }
+        };
+        if let Err(err) = socket.set_broadcast(true) {
+            warn!("Unable to enable broadcast, beacon reaches only this machine: {err}");
+        }
+        *beacon_socket = Some(BeaconSocket {
+            socket,
+            instance: u64::from_le_bytes(generate_random_bytes()),
+            next_beacon: now,
+        });
+    }
+
+    let beacon_socket = beacon_socket.as_mut().unwrap();
+    if now < beacon_socket.next_beacon {
+        return;
+    }
+    beacon_socket.next_beacon = now + BEACON_INTERVAL;
+
+    let beacon = Beacon {
+        magic: BEACON_MAGIC,
+        instance: beacon_socket.instance,
+        name: authentication
+            .config
+            .name
+            .clone()
+            .unwrap_or_else(|| DEFAULT_SERVER_NAME.to_string()),
+        players: sessions.count(),
+        max_clients: server.max_clients,
+        version: protocol.version.to_string(),
+        protocol_id: protocol.id,
+        port: server.port,
+    };
+    let message = bincode::serialize(&beacon).unwrap();
+
+    // Broadcast does not reach games on this machine on every platform, loopback does.
+    for port in DISCOVERY_PORT..DISCOVERY_PORT + DISCOVERY_PORT_COUNT {
+        for ip in [Ipv4Addr::BROADCAST, Ipv4Addr::LOCALHOST] {
+            if let Err(err) = beacon_socket.socket.send_to(&message, (ip, port)) {
+                debug!("Unable to send beacon to {ip}:{port}: {err}");
+            }
+        }
+    }
+}
+
+fn receive_beacons(mut discovered: ResMut<DiscoveredServers>, time: Res<Time>) {
+    let now = time.elapsed_seconds();
+    let discovered = &mut *discovered;
+
+    if let Some(socket) = &discovered.socket {
+        let mut buffer = [0; 512];
+        loop {
+            let (length, source) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => {
+                    debug!("Unable to receive beacon: {err}");
+                    break;
+                }
+            };
+            let Ok(beacon) = bincode::deserialize::<Beacon>(&buffer[..length]) else {
+                continue;
+            };
+            if beacon.magic != BEACON_MAGIC {
+                continue;
+            }
+
+            // Keeps the address under which the server was heard first.
+            let address = SocketAddr::new(source.ip(), beacon.port);
+            let server = discovered
+                .servers
+                .entry(beacon.instance)
+                .or_insert_with(|| DiscoveredServer {
+                    address,
+                    beacon: beacon.clone(),
+                    last_seen: now,
+                });
+            server.beacon = beacon;
+            server.last_seen = now;
+        }
+    }
+
+    discovered
+        .servers
+        .retain(|_, server| now - server.last_seen < SERVER_TIMEOUT);
+}
@@ -1,32 +1,71 @@
+pub mod authentication;
+pub mod bans;
 pub mod client;
+pub mod conditioner;
+pub mod config;
+pub mod discovery;
 pub mod network_error;
+pub mod permissions;
+pub mod protocol;
+pub mod reconnect;
 pub mod replication;
 pub mod server;
+pub mod session;
+pub mod timestep;
 
-use std::{net::IpAddr, str::FromStr};
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
 
 use bevy::prelude::*;
 use bevy_egui::{
     egui::{self, Color32},
-    EguiContexts,
+    EguiContexts, EguiPlugin,
 };
 use bevy_replicon::{
-    renet::RenetServer, replicon_core::NetworkChannels, server::TickPolicy, ReplicationPlugins,
+    renet::{RenetClient, RenetServer},
+    replicon_core::NetworkChannels,
+    server::TickPolicy,
+    ReplicationPlugins,
 };
 
-use crate::character::player::LocalPlayerResource;
+use crate::{character::player::LocalPlayerResource, cli::Args};
 
 use self::{
+    authentication::Authentication,
     client::Client,
+    conditioner::SimulatedConditions,
+    discovery::{DiscoveredServers, DiscoveryPlugin},
     network_error::NetworkError,
+    protocol::{Protocol, ProtocolPlugin},
+    reconnect::{ReconnectPlugin, ReconnectSettings, Reconnecting},
     server::{Server, ServerPlugin},
+    session::{Disconnections, LeaveSessionEvent, SessionPlugin},
+    timestep::TimestepPlugin,
 };
 
 pub const MAX_TICK_RATE: u16 = 30;
-pub const PROTOCOL_ID: u64 = 0;
 pub const DEFAULT_PORT: u16 = 13001;
+pub const DEFAULT_MAX_CLIENTS: usize = 2;
+/// Highest player limit selectable in the Multiplayer window.
+pub const MAX_CLIENTS_LIMIT: usize = 64;
+pub const DEFAULT_RECONNECT_WINDOW: f32 = 30.0;
 
-pub struct NetworkPlugin;
+pub struct NetworkPlugin {
+    pub tick_rate: u16,
+    /// Seconds during which lost connections are reconnected.
+    pub reconnect_window: f32,
+}
+
+impl Default for NetworkPlugin {
+    fn default() -> Self {
+        Self {
+            tick_rate: MAX_TICK_RATE,
+            reconnect_window: DEFAULT_RECONNECT_WINDOW,
+        }
+    }
+}
 
 impl Plugin for NetworkPlugin {
     fn build(&self, app: &mut App) {
@@ -34,16 +73,35 @@ impl Plugin for NetworkPlugin {
             ReplicationPlugins
                 .build()
                 .set(bevy_replicon::server::ServerPlugin {
-                    tick_policy: TickPolicy::MaxTickRate(MAX_TICK_RATE),
+                    tick_policy: TickPolicy::MaxTickRate(self.tick_rate),
                     ..default()
                 }),
         )
-        .insert_resource(MultiplayerUiState {
-            address: String::from_str("127.0.0.1:13001").unwrap(),
-            last_error: None,
-        })
-        .add_plugins((replication::ReplicationPlugin, ServerPlugin))
-        .add_systems(Update, ui);
+        .add_plugins((
+            ProtocolPlugin,
+            ReconnectPlugin,
+            replication::ReplicationPlugin,
+            ServerPlugin,
+            SessionPlugin,
+            TimestepPlugin,
+        ))
+        .init_resource::<SimulatedConditions>()
+        .insert_resource(ReconnectSettings {
+            window: self.reconnect_window,
+        });
+
+        // Headless server does not have any UI.
+        let has_ui = app.is_plugin_added::<EguiPlugin>();
+        app.add_plugins(DiscoveryPlugin { listen: has_ui });
+        if has_ui {
+            app.insert_resource(MultiplayerUiState {
+                address: String::from_str("127.0.0.1:13001").unwrap(),
+                max_clients: DEFAULT_MAX_CLIENTS,
+                last_error: None,
+            })
+            .add_systems(Startup, start_from_args)
+            .add_systems(Update, ui);
+        }
     }
 }
 
@@ -76,6 +134,8 @@ pub fn has_client_and_local_player(
 #[derive(Resource)]
 pub struct MultiplayerUiState {
     address: String,
+    /// Maximum number of players when hosting.
+    max_clients: usize,
     last_error: Option<String>,
 }
 
@@ -87,55 +147,271 @@ fn ui(
     commands: Commands,
     state: ResMut<MultiplayerUiState>,
     network_channels: Res<NetworkChannels>,
+    authentication: Res<Authentication>,
     server: Option<Res<RenetServer>>,
-    client: Option<Res<Client>>,
+    client: Option<Res<RenetClient>>,
+    reconnecting: Option<Res<Reconnecting>>,
+    discovered: Res<DiscoveredServers>,
+    protocol: Res<Protocol>,
+    conditions: Res<SimulatedConditions>,
+    mut disconnections: ResMut<Disconnections>,
+    mut leave_event: EventWriter<LeaveSessionEvent>,
 ) {
-    if server.is_none() && client.is_none() {
+    if server.is_some() || client.is_some() || reconnecting.is_some() {
+        egui::Window::new("Multiplayer").show(ctx.ctx_mut(), |ui| {
+            ui_session(
+                ui,
+                &state,
+                server.as_deref(),
+                client.as_deref(),
+                reconnecting.as_deref(),
+                &mut disconnections,
+                &mut leave_event,
+            );
+        });
+    } else {
         egui::Window::new("Multiplayer").show(ctx.ctx_mut(), |ui| {
             if let Some(err) = &state.last_error {
                 ui.colored_label(Color32::RED, err);
             }
+            if authentication.insecure {
+                ui.colored_label(Color32::YELLOW, "Insecure development mode");
+            }
 
-            ui_connect(meshes, materials, state, commands, network_channels, ui);
+            ui_connect(
+                meshes,
+                materials,
+                state,
+                commands,
+                network_channels,
+                authentication,
+                &conditions,
+                &discovered,
+                &protocol,
+                ui,
+            );
         });
     }
 }
 
+fn ui_session(
+    ui: &mut egui::Ui,
+    state: &MultiplayerUiState,
+    server: Option<&RenetServer>,
+    client: Option<&RenetClient>,
+    reconnecting: Option<&Reconnecting>,
+    disconnections: &mut Disconnections,
+    leave_event: &mut EventWriter<LeaveSessionEvent>,
+) {
+    if let Some(reconnecting) = reconnecting {
+        ui.colored_label(Color32::YELLOW, &reconnecting.reason);
+        ui.label(format!(
+            "Reconnecting to {}, attempt {}...",
+            state.address, reconnecting.attempt
+        ));
+        if ui.button("Cancel").clicked() {
+            leave_event.send(LeaveSessionEvent { reason: None });
+        }
+    } else if let Some(server) = server {
+        ui.label(format!(
+            "Hosting, {} players connected",
+            server.connected_clients()
+        ));
+        if disconnections.is_shutting_down() {
+            ui.label("Closing the server...");
+        } else if ui.button("Stop hosting").clicked() {
+            disconnections.shutdown();
+        }
+    } else if let Some(client) = client {
+        match client.is_connected() {
+            true => ui.label(format!("Connected to {}", state.address)),
+            false => ui.label(format!("Connecting to {}...", state.address)),
+        };
+        if ui.button("Disconnect").clicked() {
+            leave_event.send(LeaveSessionEvent { reason: None });
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn ui_connect(
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<StandardMaterial>>,
     mut state: ResMut<MultiplayerUiState>,
     commands: Commands,
     network_channels: Res<NetworkChannels>,
+    authentication: Res<Authentication>,
+    conditions: &SimulatedConditions,
+    discovered: &DiscoveredServers,
+    protocol: &Protocol,
     ui: &mut egui::Ui,
 ) {
     ui.label("Address and port");
     ui.text_edit_singleline(&mut state.address);
+    ui.horizontal(|ui| {
+        ui.label("Max players");
+        ui.add(egui::DragValue::new(&mut state.max_clients).clamp_range(1..=MAX_CLIENTS_LIMIT));
+    });
 
-    if let Err(err) = ui_connect_buttons(meshes, materials, &state, commands, network_channels, ui)
-    {
-        state.last_error = Some(err.to_string());
-    } else {
-        state.last_error = None;
+    match ui_connect_buttons(
+        meshes,
+        materials,
+        &mut state,
+        commands,
+        network_channels,
+        authentication,
+        conditions,
+        discovered,
+        protocol,
+        ui,
+    ) {
+        Ok(true) => state.last_error = None,
+        Ok(false) => {}
+        Err(err) => state.last_error = Some(err.to_string()),
     }
 }
 
+/// Returns whether connecting or hosting was started.
+#[allow(clippy::too_many_arguments)]
 fn ui_connect_buttons(
-    meshes: ResMut<Assets<Mesh>>,
-    materials: ResMut<Assets<StandardMaterial>>,
-    state: &ResMut<MultiplayerUiState>,
-    commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    state: &mut ResMut<MultiplayerUiState>,
+    mut commands: Commands,
     network_channels: Res<NetworkChannels>,
+    authentication: Res<Authentication>,
+    conditions: &SimulatedConditions,
+    discovered: &DiscoveredServers,
+    protocol: &Protocol,
     ui: &mut egui::Ui,
-) -> Result<(), NetworkError> {
+) -> Result<bool, NetworkError> {
     if ui.button("Connect").clicked() {
         let (ip, port) = parse_address_and_port(&state.address)?;
-        return client::start_connection(commands, network_channels, ip, port);
+        client::start_connection(
+            &mut commands,
+            &network_channels,
+            &authentication,
+            conditions,
+            ip,
+            port,
+        )?;
+        return Ok(true);
     } else if ui.button("Host game").clicked() {
         let (_ip, port) = parse_address_and_port(&state.address)?;
-        return server::start_listening(commands, meshes, materials, network_channels, port);
+        server::start_listening(
+            &mut commands,
+            &network_channels,
+            &authentication,
+            conditions,
+            port,
+            state.max_clients,
+        )?;
+        server::spawn_host_player(&mut commands, &mut meshes, &mut materials);
+        return Ok(true);
+    }
+
+    if let Some(address) = ui_discovered_servers(ui, discovered, protocol) {
+        state.address = address.to_string();
+        client::start_connection(
+            &mut commands,
+            &network_channels,
+            &authentication,
+            conditions,
+            address.ip(),
+            address.port(),
+        )?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Lists servers in the local network, returns address of the one player chose to join.
+fn ui_discovered_servers(
+    ui: &mut egui::Ui,
+    discovered: &DiscoveredServers,
+    protocol: &Protocol,
+) -> Option<SocketAddr> {
+    ui.separator();
+    ui.label("Local network");
+    if !discovered.is_listening() {
+        ui.colored_label(Color32::YELLOW, "Unable to search for servers");
+        return None;
+    }
+
+    let mut join = None;
+    let mut any = false;
+    for server in discovered.iter() {
+        any = true;
+        let beacon = &server.beacon;
+        let compatible = beacon.protocol_id == protocol.id;
+        ui.horizontal(|ui| {
+            ui.label(format!(
+                "{} ({}) {}/{}",
+                beacon.name, server.address, beacon.players, beacon.max_clients
+            ));
+            if !compatible {
+                ui.colored_label(Color32::RED, format!("version {}", beacon.version));
+            }
+            let button = ui.add_enabled(
+                compatible && beacon.players < beacon.max_clients,
+                egui::Button::new("Join"),
+            );
+            if button.clicked() {
+                join = Some(server.address);
+            }
+        });
+    }
+    if !any {
+        ui.label("Searching for servers...");
+    }
+    join
+}
+
+/// Connects or hosts at startup when requested by `--connect` or `--host` arguments.
+#[allow(clippy::too_many_arguments)]
+fn start_from_args(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut state: ResMut<MultiplayerUiState>,
+    network_channels: Res<NetworkChannels>,
+    authentication: Res<Authentication>,
+    conditions: Res<SimulatedConditions>,
+    args: Res<Args>,
+) {
+    state.max_clients = args.max_clients(&authentication.config);
+
+    let result = if let Some(address) = &args.connect {
+        state.address = address.clone();
+        parse_address_and_port(address).and_then(|(ip, port)| {
+            client::start_connection(
+                &mut commands,
+                &network_channels,
+                &authentication,
+                &conditions,
+                ip,
+                port,
+            )
+        })
+    } else if let Some(port) = args.host {
+        state.address = format!("127.0.0.1:{port}");
+        server::start_listening(
+            &mut commands,
+            &network_channels,
+            &authentication,
+            &conditions,
+            port,
+            state.max_clients,
+        )
+        .map(|_| server::spawn_host_player(&mut commands, &mut meshes, &mut materials))
+    } else {
+        Ok(())
+    };
+
+    if let Err(err) = result {
+        error!("Unable to start multiplayer from arguments: {err}");
+        state.last_error = Some(err.to_string());
     }
-    Ok(())
 }
 
 fn parse_address_and_port(value: &str) -> Result<(IpAddr, u16), NetworkError> {
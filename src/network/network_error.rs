@@ -1,5 +1,7 @@
 use std::{error::Error, fmt::Display};
 
+use super::protocol::RejectReason;
+
 #[derive(Debug)]
 pub enum NetworkError {
     UnableBindSocket,
@@ -8,6 +10,24 @@ pub enum NetworkError {
     MissingAddress,
     InvalidAddress,
     InvalidPort,
+    UnableReadConfig,
+    InvalidConfig,
+    MissingPrivateKey,
+    InvalidPrivateKey,
+    MissingConnectToken,
+    InvalidConnectToken,
+    UnableIssueConnectToken,
+    UnableWriteConnectToken,
+    UnableReadBanList,
+    InvalidBanList,
+    UnableWriteBanList,
+    /// Server runs build with different network protocol, its version is unknown when the server
+    /// disconnected before sending it.
+    ProtocolMismatch {
+        server_version: Option<String>,
+    },
+    /// Server refused the connection and told why.
+    Rejected(RejectReason),
 }
 
 impl Error for NetworkError {
@@ -18,18 +38,41 @@ impl Error for NetworkError {
 
 impl Display for NetworkError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                NetworkError::MissingAddress => "Missing IP address",
-                NetworkError::InvalidAddress => "Unable to parse IP address",
-                NetworkError::InvalidPort =>
-                    "Invalid port, expected decimal number from 0 to 65535",
-                NetworkError::UnableBindSocket => "Unable to bind socket",
-                NetworkError::UnableCreateServerTransport => "Unable to create server transport",
-                NetworkError::UnableCreateClientTransport => "Unable to create client transport",
+        let message = match self {
+            NetworkError::ProtocolMismatch { server_version } => {
+                return write!(
+                    f,
+                    "Server runs incompatible version {}, this game is version {}",
+                    server_version.as_deref().unwrap_or("(unknown)"),
+                    env!("CARGO_PKG_VERSION")
+                );
+            }
+            NetworkError::Rejected(reason) => return write!(f, "{reason}"),
+            NetworkError::MissingAddress => "Missing IP address",
+            NetworkError::InvalidAddress => "Unable to parse IP address",
+            NetworkError::InvalidPort => "Invalid port, expected decimal number from 0 to 65535",
+            NetworkError::UnableBindSocket => "Unable to bind socket",
+            NetworkError::UnableCreateServerTransport => "Unable to create server transport",
+            NetworkError::UnableCreateClientTransport => "Unable to create client transport",
+            NetworkError::UnableReadConfig => "Unable to read network config file",
+            NetworkError::InvalidConfig => "Unable to parse network config file",
+            NetworkError::MissingPrivateKey => {
+                "Missing private key in network config, use --insecure for development"
+            }
+            NetworkError::InvalidPrivateKey => {
+                "Invalid private key, expected 64 hexadecimal digits"
             }
-        )
+            NetworkError::MissingConnectToken => {
+                "Missing connect token, use --insecure for development"
+            }
+            NetworkError::InvalidConnectToken => "Unable to read connect token",
+            NetworkError::UnableIssueConnectToken => "Unable to issue connect token",
+            NetworkError::UnableWriteConnectToken => "Unable to write connect token",
+            NetworkError::UnableReadBanList => "Unable to read ban list file",
+            NetworkError::InvalidBanList => "Unable to parse ban list file",
+            NetworkError::UnableWriteBanList => "Unable to write ban list file",
+        };
+
+        write!(f, "{message}")
     }
 }
@@ -0,0 +1,153 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use bevy::{prelude::*, utils::HashSet};
+use bevy_replicon::prelude::*;
+
+use super::client::ClientId;
+
+/// What the player is allowed to do on the server, higher roles include lower ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Player,
+    /// Allowed to run developer commands.
+    Admin,
+    /// Player hosting the game, or the headless server itself.
+    Host,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Player => write!(f, "player"),
+            Role::Admin => write!(f, "admin"),
+            Role::Host => write!(f, "host"),
+        }
+    }
+}
+
+/// Roles of clients by id from their connect token, given by the host.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct Roles {
+    admins: HashSet<ClientId>,
+}
+
+impl Roles {
+    pub fn new(admins: impl IntoIterator<Item = u64>) -> Self {
+        Self {
+            admins: admins.into_iter().map(ClientId::from_raw).collect(),
+        }
+    }
+
+    pub fn role(&self, client_id: ClientId) -> Role {
+        if client_id == ClientId::from(SERVER_ID) {
+            Role::Host
+        } else if self.admins.contains(&client_id) {
+            Role::Admin
+        } else {
+            Role::Player
+        }
+    }
+
+    /// Changes role of the client, the host role belongs only to the server and can not be given.
+    pub fn set_role(&mut self, client_id: ClientId, role: Role) -> Result<(), HostRoleError> {
+        match role {
+            Role::Player => {
+                self.admins.remove(&client_id);
+            }
+            Role::Admin => {
+                self.admins.insert(client_id);
+            }
+            Role::Host => return Err(HostRoleError),
+        }
+        Ok(())
+    }
+
+    /// Moves role of the client to its new id after it reconnected.
+    pub fn transfer(&mut self, from: ClientId, to: ClientId) {
+        if self.admins.remove(&from) {
+            self.admins.insert(to);
+        }
+    }
+}
+
+/// Attempt to give the host role to a client.
+#[derive(Debug)]
+pub struct HostRoleError;
+
+impl fmt::Display for HostRoleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Host role can not be given")
+    }
+}
+
+/// Result of command recorded in the audit log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOutcome {
+    Executed,
+    /// Issuer does not have the role required by the command.
+    Denied,
+    /// Command was allowed, but invalid or unable to execute.
+    Failed,
+}
+
+impl fmt::Display for AuditOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditOutcome::Executed => write!(f, "executed"),
+            AuditOutcome::Denied => write!(f, "denied"),
+            AuditOutcome::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Record of every command executed on the server, appended to a file.
+#[derive(Resource)]
+pub struct AuditLog {
+    /// Path of the file, `None` once it failed to open.
+    path: Option<PathBuf>,
+    file: Option<File>,
+}
+
+impl AuditLog {
+    /// Creates log which writes into the file, it is opened on the first record.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path: Some(path),
+            file: None,
+        }
+    }
+
+    /// Records the command issued by someone with the role, and its outcome.
+    pub fn record(&mut self, issuer: &str, role: Role, command: &str, outcome: AuditOutcome) {
+        info!(target: "audit", "{issuer} ({role}) {outcome}: {command}");
+
+        if self.file.is_none() {
+            let Some(path) = &self.path else {
+                return;
+            };
+            match OpenOptions::new().create(true).append(true).open(path) {
+                Ok(file) => self.file = Some(file),
+                Err(err) => {
+                    error!("Unable to open audit log {}: {err}", path.display());
+                    self.path = None;
+                    return;
+                }
+            }
+        }
+
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let file = self.file.as_mut().unwrap();
+        if let Err(err) = writeln!(file, "{timestamp} {issuer} {role} {outcome} {command}") {
+            error!("Unable to write audit log: {err}");
+        }
+    }
+}
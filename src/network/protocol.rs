@@ -0,0 +1,373 @@
+use std::{any, fmt, time::Duration};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::{
+    bincode,
+    client::ClientSet,
+    prelude::*,
+    renet::{ChannelConfig, ConnectionConfig, DisconnectReason, SendType, ServerEvent},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    client::{ClientId, ClientSession},
+    network_error::NetworkError,
+    session::LeaveSessionEvent,
+};
+
+/// Protocol id used by netcode, changed only together with the handshake itself, so clients of
+/// incompatible builds still connect far enough to learn why they are rejected.
+pub const NETCODE_PROTOCOL_ID: u64 = 4;
+/// Renet channel of handshake and rejection messages, outside of channels allocated by replicon.
+pub const CONTROL_CHANNEL_ID: u8 = u8::MAX;
+/// Seconds after which the server disconnects clients without matching handshake.
+const HANDSHAKE_TIMEOUT: f32 = 5.0;
+/// Seconds after which the server disconnects client with mismatched handshake, so the client
+/// receives handshake of the server first.
+const REJECT_DELAY: f32 = 1.0;
+
+pub struct ProtocolPlugin;
+
+impl Plugin for ProtocolPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ProtocolTypes>()
+            .init_resource::<PendingHandshakes>()
+            .add_event::<ClientAcceptedEvent>()
+            .add_systems(
+                PreUpdate,
+                client_handshake
+                    .before(ClientSet::Receive)
+                    .run_if(resource_exists::<ClientHandshake>()),
+            )
+            .add_systems(
+                Update,
+                server_handshake.run_if(resource_exists::<RenetServer>()),
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let protocol = Protocol::new(
+            app.world.resource::<NetworkChannels>(),
+            app.world.resource::<ProtocolTypes>(),
+        );
+        info!(
+            "Protocol of version {} is {:016x}",
+            protocol.version, protocol.id
+        );
+        app.insert_resource(protocol);
+    }
+}
+
+pub trait ProtocolAppExt {
+    /// Includes type in the protocol id, should be called for every replicated component, so
+    /// builds which replicate different components do not accept each other.
+    fn add_protocol_type<T>(&mut self) -> &mut Self;
+}
+
+impl ProtocolAppExt for App {
+    fn add_protocol_type<T>(&mut self) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ProtocolTypes::default)
+            .0
+            .push(any::type_name::<T>());
+        self
+    }
+}
+
+/// Names of types included in the protocol id, in order of registration.
+#[derive(Resource, Default)]
+struct ProtocolTypes(Vec<&'static str>);
+
+/// Version of this build and id of its network protocol.
+#[derive(Debug, Clone, Resource)]
+pub struct Protocol {
+    pub version: &'static str,
+    /// Hash of the version, replicated components and channels of registered events.
+    pub id: u64,
+}
+
+impl Protocol {
+    fn new(network_channels: &NetworkChannels, types: &ProtocolTypes) -> Self {
+        let version = env!("CARGO_PKG_VERSION");
+
+        let mut hasher = Fnv::default();
+        hasher.write(version.as_bytes());
+        for name in &types.0 {
+            hasher.write(name.as_bytes());
+        }
+        for config in network_channels
+            .get_server_configs()
+            .iter()
+            .chain(&network_channels.get_client_configs())
+        {
+            hasher.write_channel(config);
+        }
+
+        Self {
+            version,
+            id: hasher.0,
+        }
+    }
+
+    fn handshake(&self, session_token: u64) -> Handshake {
+        Handshake {
+            version: self.version.to_string(),
+            protocol_id: self.id,
+            session_token,
+        }
+    }
+}
+
+/// FNV-1a hash, stable between builds unlike the standard hasher.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        // Separates consecutive writes, so "ab" + "c" differs from "a" + "bc".
+        self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    fn write_channel(&mut self, config: &ChannelConfig) {
+        let (send_type, resend_time) = match config.send_type {
+            SendType::Unreliable => (0u8, Duration::ZERO),
+            SendType::ReliableOrdered { resend_time } => (1, resend_time),
+            SendType::ReliableUnordered { resend_time } => (2, resend_time),
+        };
+        self.write(&[config.channel_id, send_type]);
+        self.write(&(config.max_memory_usage_bytes as u64).to_le_bytes());
+        self.write(&(resend_time.as_millis() as u64).to_le_bytes());
+    }
+}
+
+/// Message sent on the control channel, outside of replicon, so it is understood even by clients
+/// of incompatible builds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum ControlMessage {
+    /// Exchanged by both sides right after connecting.
+    Handshake(Handshake),
+    /// Sent by the server before it disconnects the client.
+    Rejected(RejectReason),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Handshake {
+    version: String,
+    protocol_id: u64,
+    /// Token of the client session, zero when sent by the server.
+    session_token: u64,
+}
+
+/// Why the server refused the client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RejectReason {
+    /// Server already has the maximum number of players.
+    ServerFull { max_clients: usize },
+    /// Client runs build with different network protocol.
+    VersionMismatch { server_version: String },
+    /// Client is on the ban list of the server.
+    Banned { reason: String },
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RejectReason::ServerFull { max_clients } => {
+                write!(f, "Server is full, it allows {max_clients} players")
+            }
+            RejectReason::VersionMismatch { server_version } => write!(
+                f,
+                "Server runs incompatible version {server_version}, this game is version {}",
+                env!("CARGO_PKG_VERSION")
+            ),
+            RejectReason::Banned { reason } => write!(f, "Banned from the server: {reason}"),
+        }
+    }
+}
+
+/// Sent on the server when client completes handshake, with token of its session.
+#[derive(Debug, Clone, Event)]
+pub struct ClientAcceptedEvent {
+    pub client_id: ClientId,
+    pub session_token: u64,
+}
+
+/// Handshake progress of the client, reset with every new connection.
+#[derive(Debug, Default, Resource)]
+pub struct ClientHandshake {
+    sent: bool,
+}
+
+/// Clients which have not yet sent matching handshake, with time of their disconnection.
+#[derive(Default, Resource)]
+struct PendingHandshakes(HashMap<bevy_replicon::renet::ClientId, f32>);
+
+/// Returns renet configuration with channels registered by replicon and the control channel.
+pub fn connection_config(network_channels: &NetworkChannels) -> ConnectionConfig {
+    let control_channel = ChannelConfig {
+        channel_id: CONTROL_CHANNEL_ID,
+        max_memory_usage_bytes: 64 * 1024,
+        send_type: SendType::ReliableOrdered {
+            resend_time: Duration::from_millis(300),
+        },
+    };
+
+    let mut server_channels_config = network_channels.get_server_configs();
+    server_channels_config.push(control_channel.clone());
+    let mut client_channels_config = network_channels.get_client_configs();
+    client_channels_config.push(control_channel);
+
+    ConnectionConfig {
+        server_channels_config,
+        client_channels_config,
+        ..default()
+    }
+}
+
+/// Tells the client why it is refused, the caller is responsible for disconnecting it after the
+/// message had time to arrive.
+pub fn send_rejection(
+    server: &mut RenetServer,
+    client_id: bevy_replicon::renet::ClientId,
+    reason: RejectReason,
+) {
+    let message = bincode::serialize(&ControlMessage::Rejected(reason)).unwrap();
+    server.send_message(client_id, CONTROL_CHANNEL_ID, message);
+}
+
+/// Exchanges handshake with the server and disconnects when protocols differ or the server
+/// rejects this client, before replicon receives any messages which it would be unable to
+/// deserialize.
+fn client_handshake(
+    mut leave_event: EventWriter<LeaveSessionEvent>,
+    mut client: ResMut<RenetClient>,
+    mut handshake: ResMut<ClientHandshake>,
+    mut session: ResMut<ClientSession>,
+    protocol: Res<Protocol>,
+) {
+    // Server with different channels sends messages on channels unknown to this client.
+    let mut error = match client.disconnect_reason() {
+        Some(DisconnectReason::ReceivedInvalidChannelId(_)) => {
+            Some(NetworkError::ProtocolMismatch {
+                server_version: None,
+            })
+        }
+        _ => None,
+    };
+
+    if client.is_connected() && !handshake.sent {
+        let message = ControlMessage::Handshake(protocol.handshake(session.token));
+        client.send_message(CONTROL_CHANNEL_ID, bincode::serialize(&message).unwrap());
+        handshake.sent = true;
+    }
+
+    while let Some(message) = client.receive_message(CONTROL_CHANNEL_ID) {
+        match bincode::deserialize::<ControlMessage>(&message) {
+            Ok(ControlMessage::Handshake(server)) if server.protocol_id == protocol.id => {
+                info!("Handshake accepted, server runs version {}", server.version);
+                session.established = true;
+            }
+            Ok(ControlMessage::Handshake(server)) => {
+                error = Some(NetworkError::ProtocolMismatch {
+                    server_version: Some(server.version),
+                })
+            }
+            Ok(ControlMessage::Rejected(reason)) => error = Some(NetworkError::Rejected(reason)),
+            Err(_) => {
+                error = Some(NetworkError::ProtocolMismatch {
+                    server_version: None,
+                })
+            }
+        }
+    }
+
+    if let Some(err) = error {
+        error!("Disconnected from the server: {err}");
+        client.disconnect();
+        leave_event.send(LeaveSessionEvent {
+            reason: Some(err.to_string()),
+        });
+    }
+}
+
+/// Sends handshake to connected clients and disconnects those which do not answer with
+/// matching one in time.
+fn server_handshake(
+    mut server_event: EventReader<ServerEvent>,
+    mut accepted_event: EventWriter<ClientAcceptedEvent>,
+    mut server: ResMut<RenetServer>,
+    mut pending: ResMut<PendingHandshakes>,
+    protocol: Res<Protocol>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+
+    for event in server_event.read() {
+        match event {
+            ServerEvent::ClientConnected { client_id } => {
+                let message = ControlMessage::Handshake(protocol.handshake(0));
+                server.send_message(
+                    *client_id,
+                    CONTROL_CHANNEL_ID,
+                    bincode::serialize(&message).unwrap(),
+                );
+                pending.0.insert(*client_id, now + HANDSHAKE_TIMEOUT);
+            }
+            ServerEvent::ClientDisconnected { client_id, .. } => {
+                pending.0.remove(client_id);
+            }
+        }
+    }
+
+    for client_id in server.clients_id() {
+        while let Some(message) = server.receive_message(client_id, CONTROL_CHANNEL_ID) {
+            match bincode::deserialize::<ControlMessage>(&message) {
+                Ok(ControlMessage::Handshake(client)) if client.protocol_id == protocol.id => {
+                    if pending.0.remove(&client_id).is_some() {
+                        accepted_event.send(ClientAcceptedEvent {
+                            client_id: client_id.into(),
+                            session_token: client.session_token,
+                        });
+                    }
+                }
+                Ok(ControlMessage::Handshake(client)) => {
+                    warn!(
+                        "Player {client_id} runs incompatible version {}, disconnecting.",
+                        client.version
+                    );
+                    let reason = RejectReason::VersionMismatch {
+                        server_version: protocol.version.to_string(),
+                    };
+                    send_rejection(&mut server, client_id, reason);
+                    if let Some(deadline) = pending.0.get_mut(&client_id) {
+                        *deadline = deadline.min(now + REJECT_DELAY);
+                    }
+                }
+                Ok(ControlMessage::Rejected(_)) | Err(_) => {
+                    warn!("Player {client_id} sent invalid handshake, disconnecting.");
+                    if let Some(deadline) = pending.0.get_mut(&client_id) {
+                        *deadline = deadline.min(now + REJECT_DELAY);
+                    }
+                }
+            }
+        }
+    }
+
+    pending.0.retain(|client_id, deadline| {
+        if *deadline > now {
+            return true;
+        }
+        warn!("Player {client_id} did not complete handshake in time.");
+        server.disconnect(*client_id);
+        false
+    });
+}
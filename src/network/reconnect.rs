@@ -0,0 +1,104 @@
+use bevy::prelude::*;
+use bevy_replicon::prelude::*;
+
+use super::{
+    authentication::Authentication,
+    client::{self, ClientSession},
+    conditioner::SimulatedConditions,
+    session::LeaveSessionEvent,
+};
+
+/// Seconds before the first reconnection attempt, doubled after every failed one.
+const INITIAL_BACKOFF: f32 = 0.5;
+/// Highest number of seconds between two reconnection attempts.
+const MAX_BACKOFF: f32 = 8.0;
+
+pub struct ReconnectPlugin;
+
+impl Plugin for ReconnectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, reconnect.run_if(resource_exists::<Reconnecting>()));
+    }
+}
+
+#[derive(Debug, Clone, Resource)]
+pub struct ReconnectSettings {
+    /// Seconds during which client tries to reconnect after losing connection, and the server
+    /// keeps player of such client. Zero disables reconnection.
+    pub window: f32,
+}
+
+/// Present on the client while it tries to reconnect to the server of lost session.
+#[derive(Debug, Resource)]
+pub struct Reconnecting {
+    /// Number of attempts started so far.
+    pub attempt: u32,
+    /// Why the connection was lost.
+    pub reason: String,
+    next_attempt: f32,
+    deadline: f32,
+}
+
+impl Reconnecting {
+    pub fn new(now: f32, settings: &ReconnectSettings, reason: String) -> Self {
+        Self {
+            attempt: 0,
+            reason,
+            next_attempt: now + INITIAL_BACKOFF,
+            deadline: now + settings.window,
+        }
+    }
+
+    fn backoff(&self) -> f32 {
+        (INITIAL_BACKOFF * 2f32.powi(self.attempt as i32)).min(MAX_BACKOFF)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn reconnect(
+    mut commands: Commands,
+    mut reconnecting: ResMut<Reconnecting>,
+    mut leave_event: EventWriter<LeaveSessionEvent>,
+    client: Option<Res<RenetClient>>,
+    session: Res<ClientSession>,
+    network_channels: Res<NetworkChannels>,
+    authentication: Res<Authentication>,
+    conditions: Res<SimulatedConditions>,
+    time: Res<Time>,
+) {
+    // Failed attempts are detected together with other disconnections, which removes client.
+    if let Some(client) = client {
+        if client.is_connected() {
+            info!("Reconnected after {} attempts.", reconnecting.attempt);
+            commands.remove_resource::<Reconnecting>();
+        }
+        return;
+    }
+
+    let now = time.elapsed_seconds();
+    if now >= reconnecting.deadline {
+        leave_event.send(LeaveSessionEvent {
+            reason: Some(format!("{}, unable to reconnect", reconnecting.reason)),
+        });
+        return;
+    }
+    if now < reconnecting.next_attempt {
+        return;
+    }
+
+    reconnecting.attempt += 1;
+    info!(
+        "Reconnecting to {}, attempt {}",
+        session.server_address, reconnecting.attempt
+    );
+    if let Err(err) = client::reconnect(
+        &mut commands,
+        &network_channels,
+        &authentication,
+        &conditions,
+        &session,
+    ) {
+        warn!("Unable to reconnect: {err}");
+    }
+    reconnecting.next_attempt = now + reconnecting.backoff();
+}
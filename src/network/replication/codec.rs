@@ -0,0 +1,330 @@
+//! Compact encoding of transforms replicated every tick.
+//!
+//! Translation is quantized to 16 bits per axis inside [`WORLD_MIN`] and [`WORLD_MAX`], with
+//! full precision fallback outside of them. Rotation is compressed to 32 bits by dropping its
+//! largest component, and scale is sent only when it differs from one. Typical transform takes
+//! 12 bytes instead of 40.
+//!
+//! Replicon serializes components once for all clients, without knowing which ticks they
+//! acknowledged, so transforms are not delta encoded.
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Lower corner of the box in which translation is quantized.
+pub const WORLD_MIN: Vec3 = Vec3::new(-64.0, -32.0, -64.0);
+/// Upper corner of the box in which translation is quantized.
+pub const WORLD_MAX: Vec3 = Vec3::new(64.0, 96.0, 64.0);
+/// Highest error of quantized translation on any axis, half of the quantization step over the
+/// longest span of world bounds.
+pub const TRANSLATION_PRECISION: f32 = WORLD_LONGEST_SPAN / TRANSLATION_STEPS as f32 / 2.0;
+/// Highest error of the three smallest components of decoded rotation, half of their step. The
+/// largest component is reconstructed from them, so its error is at most three times higher.
+pub const ROTATION_PRECISION: f32 = FRAC_1_SQRT_2 / ROTATION_STEPS as f32;
+
+/// Bits of each translation component.
+const TRANSLATION_BITS: u32 = u16::BITS;
+const TRANSLATION_STEPS: u32 = (1 << TRANSLATION_BITS) - 1;
+/// Longest side of the box in which translation is quantized.
+const WORLD_LONGEST_SPAN: f32 = {
+    let (x, y, z) = (
+        WORLD_MAX.x - WORLD_MIN.x,
+        WORLD_MAX.y - WORLD_MIN.y,
+        WORLD_MAX.z - WORLD_MIN.z,
+    );
+    let xy = if x > y { x } else { y };
+    if xy > z {
+        xy
+    } else {
+        z
+    }
+};
+
+/// Bits of each of the three smallest rotation components.
+const ROTATION_BITS: u32 = 10;
+const ROTATION_STEPS: u32 = (1 << ROTATION_BITS) - 1;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct QuantizedTransform {
+    translation: [u16; 3],
+    /// Translation outside of world bounds, sent with full precision.
+    unbounded_translation: Option<Vec3>,
+    rotation: u32,
+    /// Scale, `None` when it is one.
+    scale: Option<Vec3>,
+}
+
+impl From<&Transform> for QuantizedTransform {
+    fn from(transform: &Transform) -> Self {
+        let in_bounds = transform.translation.cmpge(WORLD_MIN).all()
+            && transform.translation.cmple(WORLD_MAX).all();
+
+        Self {
+            translation: match in_bounds {
+                true => quantize_translation(transform.translation),
+                false => [0; 3],
+            },
+            unbounded_translation: (!in_bounds).then_some(transform.translation),
+            rotation: compress_rotation(transform.rotation),
+            scale: (transform.scale != Vec3::ONE).then_some(transform.scale),
+        }
+    }
+}
+
+impl From<QuantizedTransform> for Transform {
+    fn from(value: QuantizedTransform) -> Self {
+        Self {
+            translation: value
+                .unbounded_translation
+                .unwrap_or_else(|| dequantize_translation(value.translation)),
+            rotation: decompress_rotation(value.rotation),
+            scale: value.scale.unwrap_or(Vec3::ONE),
+        }
+    }
+}
+
+fn quantize_translation(translation: Vec3) -> [u16; 3] {
+    let normalized = (translation - WORLD_MIN) / (WORLD_MAX - WORLD_MIN);
+    let quantized = (normalized * TRANSLATION_STEPS as f32).round();
+    [quantized.x as u16, quantized.y as u16, quantized.z as u16]
+}
+
+fn dequantize_translation(translation: [u16; 3]) -> Vec3 {
+    let normalized = Vec3::new(
+        translation[0] as f32,
+        translation[1] as f32,
+        translation[2] as f32,
+    ) / TRANSLATION_STEPS as f32;
+    WORLD_MIN + normalized * (WORLD_MAX - WORLD_MIN)
+}
+
+/// Packs index of the largest component into the top two bits, followed by the other three
+/// components, which always lie between -1/√2 and 1/√2.
+fn compress_rotation(rotation: Quat) -> u32 {
+    let mut components = rotation.normalize().to_array();
+    let (largest, _) = components
+        .iter()
+        .enumerate()
+        .fold((0, 0.0), |(index, max), (i, x)| match x.abs() > max {
+            true => (i, x.abs()),
+            false => (index, max),
+        });
+    // Rotations q and -q are the same, so the largest component is kept positive.
+    if components[largest] < 0.0 {
+        components = components.map(|x| -x);
+    }
+
+    let mut packed = 0;
+    for (i, component) in components.iter().enumerate() {
+        if i == largest {
+            continue;
+        }
+        let normalized = (component / FRAC_1_SQRT_2 + 1.0) / 2.0;
+        let quantized = (normalized.clamp(0.0, 1.0) * ROTATION_STEPS as f32).round() as u32;
+        packed = (packed << ROTATION_BITS) | quantized;
+    }
+    packed | ((largest as u32) << (ROTATION_BITS * 3))
+}
+
+fn decompress_rotation(packed: u32) -> Quat {
+    let largest = ((packed >> (ROTATION_BITS * 3)) & 0b11) as usize;
+
+    let mut components = [0.0; 4];
+    let mut shift = ROTATION_BITS * 3;
+    let mut sum = 0.0;
+    for (i, component) in components.iter_mut().enumerate() {
+        if i == largest {
+            continue;
+        }
+        shift -= ROTATION_BITS;
+        let quantized = (packed >> shift) & ROTATION_STEPS;
+        let value = (quantized as f32 / ROTATION_STEPS as f32 * 2.0 - 1.0) * FRAC_1_SQRT_2;
+        *component = value;
+        sum += value * value;
+    }
+    components[largest] = (1.0 - sum).max(0.0).sqrt();
+
+    Quat::from_array(components).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Rounding of `f32` arithmetic on top of the quantization error.
+    const EPSILON: f32 = 1e-5;
+
+    fn round_trip(transform: Transform) -> Transform {
+        QuantizedTransform::from(&transform).into()
+    }
+
+    /// Deterministic pseudo-random numbers from -1 to 1, so failures are reproducible.
+    fn random_numbers() -> impl FnMut() -> f32 {
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 40) as f32 / (1 << 24) as f32 * 2.0 - 1.0
+        }
+    }
+
+    fn assert_rotation_within_precision(rotation: Quat) {
+        let decoded = round_trip(Transform::from_rotation(rotation)).rotation;
+        // Rotations q and -q are the same, compare with the one closer to the original.
+        let decoded = match decoded.dot(rotation) < 0.0 {
+            true => -decoded,
+            false => decoded,
+        };
+
+        let original = rotation.to_array();
+        let decoded = decoded.to_array();
+        // With ties any of the largest components may be the reconstructed one.
+        let largest = original.iter().fold(0.0, |max: f32, x| max.max(x.abs()));
+        for i in 0..4 {
+            let precision = match original[i].abs() == largest {
+                true => ROTATION_PRECISION * 3.0,
+                false => ROTATION_PRECISION,
+            };
+            let error = (original[i] - decoded[i]).abs();
+            assert!(
+                error <= precision + EPSILON,
+                "component {i} of {rotation} decoded as {decoded:?} with error {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn translation_within_precision_across_bounds() {
+        const SAMPLES: u32 = 100_000;
+        for i in 0..=SAMPLES {
+            let t = i as f32 / SAMPLES as f32;
+            let diagonal = WORLD_MIN.lerp(WORLD_MAX, t);
+            // Diagonal, and each axis separately with the others at their bounds.
+            for translation in [
+                diagonal,
+                Vec3::new(diagonal.x, WORLD_MIN.y, WORLD_MAX.z),
+                Vec3::new(WORLD_MAX.x, diagonal.y, WORLD_MIN.z),
+                Vec3::new(WORLD_MIN.x, WORLD_MAX.y, diagonal.z),
+            ] {
+                let quantized = QuantizedTransform::from(&Transform::from_translation(translation));
+                assert!(quantized.unbounded_translation.is_none());
+
+                let decoded = Transform::from(quantized).translation;
+                let error = (decoded - translation).abs().max_element();
+                assert!(
+                    error <= TRANSLATION_PRECISION + EPSILON,
+                    "{translation} decoded as {decoded} with error {error}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn translation_at_edges_of_bounds_round_trips() {
+        let size = WORLD_MAX - WORLD_MIN;
+        for corner in 0..8 {
+            let select = |bit: u32, axis: usize| match corner & (1 << bit) != 0 {
+                true => WORLD_MAX[axis],
+                false => WORLD_MIN[axis],
+            };
+            let translation = Vec3::new(select(0, 0), select(1, 1), select(2, 2));
+            let quantized = QuantizedTransform::from(&Transform::from_translation(translation));
+            assert!(quantized.unbounded_translation.is_none());
+            assert_eq!(Transform::from(quantized).translation, translation);
+
+            // Values one step inside of the bounds stay apart from the edges.
+            let step = size / TRANSLATION_STEPS as f32;
+            let inside = translation + (WORLD_MIN + WORLD_MAX - translation * 2.0).signum() * step;
+            let quantized = QuantizedTransform::from(&Transform::from_translation(inside));
+            let decoded = Transform::from(quantized).translation;
+            assert!(decoded.cmpne(translation).all());
+            let error = (decoded - inside).abs().max_element();
+            assert!(
+                error <= TRANSLATION_PRECISION + EPSILON,
+                "{inside} decoded as {decoded} with error {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn translation_out_of_bounds_uses_full_precision() {
+        for translation in [
+            WORLD_MAX + Vec3::X * 0.001,
+            WORLD_MIN - Vec3::Y * 0.001,
+            Vec3::new(1000.123, 0.5, -0.25),
+            Vec3::new(0.0, -500.0, 0.0),
+        ] {
+            let quantized = QuantizedTransform::from(&Transform::from_translation(translation));
+            assert_eq!(quantized.unbounded_translation, Some(translation));
+            assert_eq!(Transform::from(quantized).translation, translation);
+        }
+    }
+
+    #[test]
+    fn rotation_within_precision_for_random_rotations() {
+        let mut random = random_numbers();
+        for _ in 0..100_000 {
+            let rotation = Quat::from_xyzw(random(), random(), random(), random());
+            if rotation.length_squared() > EPSILON {
+                assert_rotation_within_precision(rotation.normalize());
+            }
+        }
+    }
+
+    #[test]
+    fn rotation_within_precision_for_edge_cases() {
+        assert_rotation_within_precision(Quat::IDENTITY);
+        assert_rotation_within_precision(Quat::from_xyzw(0.5, 0.5, 0.5, 0.5));
+        assert_rotation_within_precision(Quat::from_xyzw(-0.5, 0.5, -0.5, 0.5));
+        // Two components of 1/√2, the bounds of the range of the smallest components.
+        assert_rotation_within_precision(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2));
+        assert_rotation_within_precision(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2));
+
+        // Each component as the largest, both positive and negative.
+        let components = [0.9, 0.1, -0.3, 0.2];
+        for largest in 0..4 {
+            let mut array = components;
+            array.swap(0, largest);
+            let rotation = Quat::from_array(array).normalize();
+            assert_rotation_within_precision(rotation);
+            assert_rotation_within_precision(-rotation);
+        }
+    }
+
+    #[test]
+    fn negated_rotation_encodes_the_same() {
+        let mut random = random_numbers();
+        for _ in 0..1000 {
+            let rotation = Quat::from_xyzw(random(), random(), random(), random()).normalize();
+            assert_eq!(
+                QuantizedTransform::from(&Transform::from_rotation(rotation)).rotation,
+                QuantizedTransform::from(&Transform::from_rotation(-rotation)).rotation,
+            );
+        }
+        assert_eq!(
+            compress_rotation(Quat::IDENTITY),
+            compress_rotation(-Quat::IDENTITY)
+        );
+    }
+
+    #[test]
+    fn unit_scale_is_omitted() {
+        let transform = Transform::from_xyz(1.0, 2.0, 3.0);
+        let quantized = QuantizedTransform::from(&transform);
+        assert_eq!(quantized.scale, None);
+        assert_eq!(Transform::from(quantized).scale, Vec3::ONE);
+    }
+
+    #[test]
+    fn non_unit_scale_is_preserved() {
+        for scale in [Vec3::splat(2.0), Vec3::new(0.5, 1.0, 1.0), Vec3::ZERO] {
+            let transform = Transform::from_xyz(1.0, 2.0, 3.0).with_scale(scale);
+            let quantized = QuantizedTransform::from(&transform);
+            assert_eq!(quantized.scale, Some(scale));
+            assert_eq!(Transform::from(quantized).scale, scale);
+        }
+    }
+}
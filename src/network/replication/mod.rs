@@ -1,3 +1,4 @@
+pub mod codec;
 pub mod transform;
 
 use bevy::prelude::*;
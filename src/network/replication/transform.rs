@@ -1,10 +1,23 @@
-use bevy::{prelude::*, ptr::Ptr};
-use bevy_replicon::{bincode, prelude::*, replicon_core::replication_rules};
+use bevy::{prelude::*, ptr::Ptr, time::Real};
+use bevy_replicon::{bincode, client::ClientSet, prelude::*, replicon_core::replication_rules};
 use serde::{Deserialize, Serialize};
-use std::io::Cursor;
+use std::{collections::VecDeque, io::Cursor};
 
-use crate::{math::lerp_exponent_in_time, network::MAX_TICK_RATE};
+use crate::network::{has_client, protocol::ProtocolAppExt, MAX_TICK_RATE};
 
+use super::codec::QuantizedTransform;
+
+/// Maximum number of snapshots stored for a single entity.
+const MAX_SNAPSHOTS: usize = 32;
+/// Part of the difference between received and estimated tick which is corrected per received
+/// tick, higher values follow the server faster, but pass network jitter to rendering.
+const CLOCK_CORRECTION: f64 = 0.1;
+/// Part of the difference between received and estimated tick which is added to the estimated
+/// tick rate per received tick, so the clock does not drift when server ticks at other rate.
+const TICK_RATE_CORRECTION: f64 = 0.05;
+
+/// Replicates transforms of all replicated entities. Changes travel in replicon update messages,
+/// which are unreliable and applied only when newer than the last update of the entity.
 pub struct TransformPlugin;
 
 impl Plugin for TransformPlugin {
@@ -14,11 +27,86 @@ impl Plugin for TransformPlugin {
             deserialize_transform,
             replication_rules::remove_component::<Transform>,
         )
-        .add_systems(PreUpdate, apply_synced_transform);
+        .add_protocol_type::<QuantizedTransform>()
+        .init_resource::<InterpolationSettings>()
+        .init_resource::<InterpolationClock>()
+        .add_systems(
+            PreUpdate,
+            (update_interpolation_clock, interpolate_snapshots)
+                .chain()
+                .after(ClientSet::Receive)
+                .run_if(has_client),
+        );
     }
 }
 
-#[derive(Clone, Serialize, Deserialize, Component)]
+/// Settings of rendering remote entities from received snapshots.
+#[derive(Debug, Clone, Resource)]
+pub struct InterpolationSettings {
+    /// How far in the past, in seconds, remote entities are rendered. Bigger delay hides more
+    /// jitter and packet loss at the cost of showing older state.
+    pub delay: f64,
+    /// For how long, in seconds, movement is extrapolated when there are no newer snapshots.
+    pub max_extrapolation: f64,
+}
+
+impl Default for InterpolationSettings {
+    fn default() -> Self {
+        Self {
+            delay: 0.1,
+            max_extrapolation: 0.25,
+        }
+    }
+}
+
+/// Client estimation of the current server tick, advanced smoothly between received ticks.
+#[derive(Debug, Clone, Resource)]
+pub struct InterpolationClock {
+    current: Option<f64>,
+    tick_rate: f64,
+}
+
+impl Default for InterpolationClock {
+    fn default() -> Self {
+        Self {
+            current: None,
+            tick_rate: MAX_TICK_RATE as f64,
+        }
+    }
+}
+
+impl InterpolationClock {
+    fn receive(&mut self, tick: RepliconTick) {
+        let tick = tick.get() as f64;
+        let Some(current) = self.current else {
+            self.current = Some(tick);
+            return;
+        };
+
+        let error = tick - current;
+        if error.abs() > self.tick_rate {
+            // Too far away to catch up smoothly, e.g. after a long stall.
+            self.current = Some(tick);
+            return;
+        }
+
+        self.current = Some(current + error * CLOCK_CORRECTION);
+        self.tick_rate = (self.tick_rate + error * TICK_RATE_CORRECTION).max(1.0);
+    }
+
+    /// Tick, possibly fractional, at which remote entities should be rendered.
+    pub fn render_tick(&self, settings: &InterpolationSettings) -> Option<f64> {
+        self.current
+            .map(|current| current - settings.delay * self.tick_rate)
+    }
+
+    /// Estimated number of server ticks per second.
+    pub fn tick_rate(&self) -> f64 {
+        self.tick_rate
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SyncedTransform {
     translation: Vec3,
     rotation: Quat,
@@ -45,33 +133,139 @@ impl From<SyncedTransform> for Transform {
     }
 }
 
-fn apply_synced_transform(mut query: Query<(&mut Transform, &SyncedTransform)>, time: Res<Time>) {
-    let t = lerp_exponent_in_time(1.0 / MAX_TICK_RATE as f32, 0.0001, time.delta_seconds());
+/// Marks entity whose transform is simulated locally, replicated transforms are ignored.
+#[derive(Component, Default)]
+pub struct Predicted;
+
+/// Transforms of remote entity received from the server, ordered by tick.
+#[derive(Component, Default)]
+pub struct SnapshotBuffer {
+    snapshots: VecDeque<(RepliconTick, SyncedTransform)>,
+}
+
+impl SnapshotBuffer {
+    pub fn insert(&mut self, tick: RepliconTick, transform: SyncedTransform) {
+        let index = self.snapshots.partition_point(|(x, _)| is_newer(tick, *x));
+        match self.snapshots.get_mut(index) {
+            Some((x, snapshot)) if *x == tick => *snapshot = transform,
+            _ => self.snapshots.insert(index, (tick, transform)),
+        }
+
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Returns transform at the given tick, interpolated between bracketing snapshots or
+    /// extrapolated from the last two, for up to `max_extrapolation` ticks.
+    fn sample(&self, render_tick: f64, max_extrapolation: f64) -> Option<Transform> {
+        let (last_tick, last) = self.snapshots.back()?;
+        let last_tick = last_tick.get() as f64;
+
+        if render_tick >= last_tick {
+            let mut transform = Transform::from(last.clone());
+            if let Some((previous_tick, previous)) = self.snapshots.iter().rev().nth(1) {
+                let ticks = last_tick - previous_tick.get() as f64;
+                let velocity = (last.translation - previous.translation) / ticks as f32;
+                let extrapolated = (render_tick - last_tick).min(max_extrapolation);
+                transform.translation += velocity * extrapolated as f32;
+            }
+            return Some(transform);
+        }
+
+        let index = self
+            .snapshots
+            .partition_point(|(x, _)| x.get() as f64 <= render_tick);
+        if index == 0 {
+            return self.snapshots.front().map(|x| x.1.clone().into());
+        }
+
+        let (from_tick, from) = &self.snapshots[index - 1];
+        let (to_tick, to) = &self.snapshots[index];
+        let from_tick = from_tick.get() as f64;
+        let t = ((render_tick - from_tick) / (to_tick.get() as f64 - from_tick)) as f32;
+
+        Some(Transform {
+            translation: from.translation.lerp(to.translation, t),
+            rotation: from.rotation.slerp(to.rotation, t),
+            scale: from.scale.lerp(to.scale, t),
+        })
+    }
+
+    /// Removes snapshots which are no longer needed to sample the given tick.
+    fn prune(&mut self, render_tick: f64) {
+        while self.snapshots.len() > 2 && self.snapshots[1].0.get() as f64 <= render_tick {
+            self.snapshots.pop_front();
+        }
+    }
+}
+
+fn is_newer(tick: RepliconTick, other: RepliconTick) -> bool {
+    tick.get().wrapping_sub(other.get()) as i32 > 0
+}
+
+fn update_interpolation_clock(
+    mut clock: ResMut<InterpolationClock>,
+    tick: Res<RepliconTick>,
+    time: Res<Time<Real>>,
+) {
+    let tick_rate = clock.tick_rate;
+    if let Some(current) = clock.current.as_mut() {
+        *current += time.delta_seconds_f64() * tick_rate;
+    }
+
+    if tick.is_changed() {
+        clock.receive(*tick);
+    }
+}
+
+fn interpolate_snapshots(
+    mut query: Query<(&mut Transform, &mut SnapshotBuffer)>,
+    clock: Res<InterpolationClock>,
+    settings: Res<InterpolationSettings>,
+) {
+    let Some(render_tick) = clock.render_tick(&settings) else {
+        return;
+    };
+    let max_extrapolation = settings.max_extrapolation * clock.tick_rate;
 
-    for (mut transform, synced_transform) in query.iter_mut() {
-        transform.translation = transform.translation.lerp(synced_transform.translation, t);
-        transform.rotation = transform.rotation.lerp(synced_transform.rotation, t);
-        transform.scale = synced_transform.scale;
+    for (mut transform, mut buffer) in query.iter_mut() {
+        if let Some(sampled) = buffer.sample(render_tick, max_extrapolation) {
+            *transform = sampled;
+        }
+        buffer.prune(render_tick);
     }
 }
 
 fn serialize_transform(component: Ptr, cursor: &mut Cursor<Vec<u8>>) -> bincode::Result<()> {
     // SAFETY: Function called for registered `ComponentId`.
     let transform: &Transform = unsafe { component.deref() };
-    bincode::serialize_into(cursor, &SyncedTransform::from(*transform))
+    bincode::serialize_into(cursor, &QuantizedTransform::from(transform))
 }
 
 fn deserialize_transform(
     entity: &mut EntityWorldMut,
     _entity_map: &mut ServerEntityMap,
     cursor: &mut Cursor<&[u8]>,
-    _replicon_tick: RepliconTick,
+    replicon_tick: RepliconTick,
 ) -> bincode::Result<()> {
-    let transform: SyncedTransform = bincode::deserialize_from(cursor)?;
+    let transform: QuantizedTransform = bincode::deserialize_from(cursor)?;
+    let transform = SyncedTransform::from(Transform::from(transform));
+    if entity.contains::<Predicted>() {
+        return Ok(());
+    }
     if entity.get::<Transform>().is_none() {
         entity.insert(Transform::from(transform.clone()));
     }
-    entity.insert(transform);
+
+    match entity.get_mut::<SnapshotBuffer>() {
+        Some(mut buffer) => buffer.insert(replicon_tick, transform),
+        None => {
+            let mut buffer = SnapshotBuffer::default();
+            buffer.insert(replicon_tick, transform);
+            entity.insert(buffer);
+        }
+    }
 
     Ok(())
 }
@@ -3,113 +3,334 @@ use std::{
     time::SystemTime,
 };
 
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashMap};
 use bevy_replicon::{
+    network_event::{client_event::FromClient, server_event::ToClients},
     prelude::*,
     renet::{
-        transport::{NetcodeServerTransport, ServerAuthentication, ServerConfig},
-        ConnectionConfig, ServerEvent,
+        transport::{NetcodeServerTransport, ServerConfig},
+        DisconnectReason, ServerEvent,
     },
 };
 
-use crate::character::player::{self, Player};
+use crate::{
+    character::player::{
+        self, input::InputQueue, registry::PlayerRegistry, validation::MovementViolations, Player,
+    },
+    chat::ChatServerEvent,
+    lobby,
+};
 
-use super::network_error::NetworkError;
+use super::{
+    authentication::Authentication,
+    bans::BanList,
+    client::ClientId,
+    conditioner::{ConditionerRelay, SimulatedConditions},
+    network_error::NetworkError,
+    permissions::Roles,
+    protocol::{self, ClientAcceptedEvent, RejectReason, NETCODE_PROTOCOL_ID},
+    reconnect::ReconnectSettings,
+    session::{Disconnections, LeaveClientEvent},
+};
 
 pub struct ServerPlugin;
 
 impl Plugin for ServerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
+        app.init_resource::<PlayerSessions>().add_systems(
             Update,
-            process_server_events.run_if(resource_exists::<RenetServer>()),
+            (
+                process_server_events,
+                accept_clients,
+                leave_server_handler,
+                expire_sessions,
+            )
+                .run_if(resource_exists::<RenetServer>()),
         );
     }
 }
 
+/// Number of connections accepted by netcode above the player limit, so clients of full server
+/// connect long enough to be told why they are rejected.
+const REJECTION_SLOTS: usize = 4;
+
 #[derive(Resource)]
-pub struct Server;
+pub struct Server {
+    /// Maximum number of players, including those which wait for reconnection.
+    pub max_clients: usize,
+    /// Port on which the server listens.
+    pub port: u16,
+}
+
+/// Players of clients, by token of their session, kept for a while after connection is lost.
+#[derive(Resource, Default)]
+pub struct PlayerSessions {
+    sessions: HashMap<u64, PlayerSession>,
+}
+
+struct PlayerSession {
+    entity: Entity,
+    client_id: ClientId,
+    /// Time at which connection was lost, `None` while connected.
+    disconnected_at: Option<f32>,
+}
+
+impl PlayerSessions {
+    /// Number of players of clients, including those which wait for reconnection.
+    pub fn count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    fn find_mut(&mut self, client_id: ClientId) -> Option<(u64, &mut PlayerSession)> {
+        self.sessions
+            .iter_mut()
+            .find(|(_, session)| session.client_id == client_id)
+            .map(|(token, session)| (*token, session))
+    }
+}
 
 pub fn start_listening(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    network_channels: Res<NetworkChannels>,
+    commands: &mut Commands,
+    network_channels: &NetworkChannels,
+    authentication: &Authentication,
+    conditions: &SimulatedConditions,
     server_port: u16,
+    max_clients: usize,
 ) -> Result<(), NetworkError> {
-    let server = RenetServer::new(ConnectionConfig {
-        server_channels_config: network_channels.get_server_configs(),
-        client_channels_config: network_channels.get_client_configs(),
-        ..default()
-    });
+    let server = RenetServer::new(protocol::connection_config(network_channels));
 
     let public_address = SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), server_port);
-    let socket = UdpSocket::bind(public_address).map_err(|_| NetworkError::UnableBindSocket)?;
+    let (authentication, public_addresses) = authentication.server(public_address)?;
+    // Relay is kept only once the server starts, dropping it stops its thread.
+    let mut relay = None;
+    let socket = match conditions.is_enabled() {
+        // Clients reach the transport only through the relay, which listens on the public port.
+        true => {
+            let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+                .map_err(|_| NetworkError::UnableBindSocket)?;
+            let target = socket
+                .local_addr()
+                .map_err(|_| NetworkError::UnableBindSocket)?;
+            relay = Some(
+                ConditionerRelay::spawn(public_address, target, conditions)
+                    .map_err(|_| NetworkError::UnableBindSocket)?,
+            );
+            socket
+        }
+        false => UdpSocket::bind(public_address).map_err(|_| NetworkError::UnableBindSocket)?,
+    };
     let server_config = ServerConfig {
         current_time: SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap(),
-        max_clients: 2,
-        protocol_id: super::PROTOCOL_ID,
-        authentication: ServerAuthentication::Unsecure,
-        public_addresses: vec![public_address],
+        max_clients: max_clients + REJECTION_SLOTS,
+        protocol_id: NETCODE_PROTOCOL_ID,
+        authentication,
+        public_addresses,
     };
     let transport = NetcodeServerTransport::new(server_config, socket)
         .map_err(|_| NetworkError::UnableCreateServerTransport)?;
 
     info!("Server started on {}", public_address);
 
+    if let Some(relay) = relay {
+        commands.insert_resource(relay);
+    }
     commands.insert_resource(server);
     commands.insert_resource(transport);
-    commands.insert_resource(Server);
+    commands.insert_resource(Server {
+        max_clients,
+        port: server_port,
+    });
 
+    Ok(())
+}
+
+/// Spawns local player of the server, used when game is hosted from client instead of headless.
+pub fn spawn_host_player(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+) {
     player::spawn(
-        &mut commands,
-        &mut meshes,
-        &mut materials,
-        player::Player {
-            client_id: SERVER_ID.into(),
-            attached_camera: None,
-        },
+        commands,
+        meshes,
+        materials,
+        player::Player::new(SERVER_ID.into(), 0),
         player::PlayerKind::Local,
     );
-
-    Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn process_server_events(
     mut server_event: EventReader<ServerEvent>,
+    mut chat_event: EventWriter<ToClients<ChatServerEvent>>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    query: Query<(Entity, &Player)>,
+    mut sessions: ResMut<PlayerSessions>,
+    players: Query<&Player>,
+    registry: Res<PlayerRegistry>,
+    settings: Res<ReconnectSettings>,
+    time: Res<Time>,
 ) {
     for event in server_event.read() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
                 info!("Player {client_id} connected.");
-
-                player::spawn(
-                    &mut commands,
-                    &mut meshes,
-                    &mut materials,
-                    player::Player {
-                        client_id: (*client_id).into(),
-                        attached_camera: None,
-                    },
-                    player::PlayerKind::Remote,
-                );
+                chat_event.send(ChatServerEvent::system(format!(
+                    "Player {} connected.",
+                    ClientId::from(*client_id)
+                )));
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
                 info!("Player {client_id} disconnected: {reason}");
+                let name = registry
+                    .entity((*client_id).into())
+                    .and_then(|entity| players.get(entity).ok())
+                    .map_or_else(
+                        || format!("Player {}", ClientId::from(*client_id)),
+                        |x| x.to_string(),
+                    );
+                chat_event.send(ChatServerEvent::system(format!("{name} disconnected.")));
 
-                query
-                    .iter()
-                    .filter(|x| x.1.client_id == client_id)
-                    .for_each(|x| {
-                        commands.entity(x.0).despawn_recursive();
-                    });
+                let Some((token, session)) = sessions.find_mut((*client_id).into()) else {
+                    continue;
+                };
+                // Only connections lost in transport may come back, kicked players do not.
+                if matches!(reason, DisconnectReason::Transport) && settings.window > 0.0 {
+                    info!("Keeping player {client_id} for reconnection.");
+                    session.disconnected_at = Some(time.elapsed_seconds());
+                } else {
+                    commands.entity(session.entity).despawn_recursive();
+                    sessions.sessions.remove(&token);
+                }
             }
         }
     }
 }
+
+/// Spawns players of clients which completed handshake, or gives them back their player when
+/// they reconnect to session which is still kept.
+#[allow(clippy::too_many_arguments)]
+fn accept_clients(
+    mut accepted_event: EventReader<ClientAcceptedEvent>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut sessions: ResMut<PlayerSessions>,
+    mut disconnections: ResMut<Disconnections>,
+    mut roles: ResMut<Roles>,
+    mut violations: ResMut<MovementViolations>,
+    mut players: Query<&mut Player>,
+    server: Res<Server>,
+    transport: Res<NetcodeServerTransport>,
+    ban_list: Res<BanList>,
+) {
+    for ClientAcceptedEvent {
+        client_id,
+        session_token,
+    } in accepted_event.read()
+    {
+        let ip = transport.client_addr((*client_id).into()).map(|x| x.ip());
+        // Client may be banned while waiting for reconnection, by id of its lost connection.
+        let previous_id = sessions.sessions.get(session_token).map(|x| x.client_id);
+        let ban = ban_list
+            .find(*client_id, ip)
+            .or_else(|| previous_id.and_then(|x| ban_list.find(x, None)));
+        if let Some(ban) = ban {
+            disconnections.reject(
+                *client_id,
+                RejectReason::Banned {
+                    reason: ban.reason.clone(),
+                },
+            );
+            continue;
+        }
+
+        if let Some(session) = sessions.sessions.get_mut(session_token) {
+            if session.disconnected_at.is_none() {
+                warn!(
+                    "Player {client_id} used session of connected player {}.",
+                    session.client_id
+                );
+                disconnections.kick(*client_id, "Session is already in use");
+                continue;
+            }
+
+            if let Ok(mut player) = players.get_mut(session.entity) {
+                info!("Player {} reconnected as {client_id}.", session.client_id);
+                player.client_id = *client_id;
+                // State kept by client id follows the player to its new connection.
+                roles.transfer(session.client_id, *client_id);
+                violations.transfer(session.client_id, *client_id);
+                // Sequence numbers of inputs start again with the new connection.
+                commands
+                    .entity(session.entity)
+                    .insert(InputQueue::default());
+                session.client_id = *client_id;
+                session.disconnected_at = None;
+                continue;
+            }
+        }
+
+        if sessions.sessions.len() >= server.max_clients {
+            disconnections.reject(
+                *client_id,
+                RejectReason::ServerFull {
+                    max_clients: server.max_clients,
+                },
+            );
+            continue;
+        }
+
+        let entity = player::spawn(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            player::Player::new(*client_id, lobby::next_color(players.iter())),
+            player::PlayerKind::Simulated,
+        );
+        sessions.sessions.insert(
+            *session_token,
+            PlayerSession {
+                entity,
+                client_id: *client_id,
+                disconnected_at: None,
+            },
+        );
+    }
+}
+
+/// Removes player of client which left on its own, without waiting for reconnection.
+fn leave_server_handler(
+    mut leave_event: EventReader<FromClient<LeaveClientEvent>>,
+    mut commands: Commands,
+    mut sessions: ResMut<PlayerSessions>,
+) {
+    for FromClient { client_id, .. } in leave_event.read() {
+        info!("Player {client_id} is leaving.");
+        if let Some((token, session)) = sessions.find_mut((*client_id).into()) {
+            commands.entity(session.entity).despawn_recursive();
+            sessions.sessions.remove(&token);
+        }
+    }
+}
+
+/// Despawns players of clients which did not reconnect in time.
+fn expire_sessions(
+    mut commands: Commands,
+    mut sessions: ResMut<PlayerSessions>,
+    settings: Res<ReconnectSettings>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+    sessions
+        .sessions
+        .retain(|_, session| match session.disconnected_at {
+            Some(disconnected_at) if now - disconnected_at > settings.window => {
+                info!("Player {} did not reconnect in time.", session.client_id);
+                commands.entity(session.entity).despawn_recursive();
+                false
+            }
+            _ => true,
+        });
+}
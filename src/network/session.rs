@@ -0,0 +1,338 @@
+use std::fmt;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_replicon::{
+    client::ClientSet,
+    network_event::{
+        client_event::ClientEventAppExt,
+        server_event::{SendMode, ServerEventAppExt, ToClients},
+        EventType,
+    },
+    prelude::*,
+    renet::transport::{NetcodeClientTransport, NetcodeDisconnectReason, NetcodeServerTransport},
+    replicon_core::replication_rules::Replication,
+    server::ServerSet,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::character::player::LocalPlayerResource;
+
+use super::{
+    client::{Client, ClientId, ClientSession},
+    conditioner::ConditionerRelay,
+    protocol::{self, ClientHandshake, RejectReason},
+    reconnect::{ReconnectSettings, Reconnecting},
+    replication::transform::InterpolationClock,
+    server::{PlayerSessions, Server},
+    MultiplayerUiState,
+};
+
+/// Seconds between telling client why it is disconnected and disconnecting it, so the reason
+/// arrives before the connection is closed.
+const DISCONNECT_DELAY: f32 = 0.5;
+
+pub struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_server_event::<DisconnectServerEvent>(EventType::Ordered)
+            .add_client_event::<LeaveClientEvent>(EventType::Ordered)
+            .add_event::<LeaveSessionEvent>()
+            .init_resource::<Disconnections>()
+            .add_systems(
+                PreUpdate,
+                detect_client_disconnection
+                    .after(ClientSet::Receive)
+                    .run_if(resource_exists::<RenetClient>()),
+            )
+            .add_systems(
+                PostUpdate,
+                notify_leave
+                    .before(ClientSet::Send)
+                    .run_if(resource_exists::<RenetClient>()),
+            )
+            .add_systems(
+                PostUpdate,
+                process_disconnections
+                    .before(ServerSet::Send)
+                    .run_if(resource_exists::<RenetServer>()),
+            )
+            .add_systems(Last, leave_session);
+    }
+}
+
+/// Why the session ended, shown to players when they come back to the connect screen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DisconnectReason {
+    /// Server stopped hosting the game.
+    ServerClosed,
+    /// Server disconnected the client, with explanation.
+    Kicked(String),
+    /// Server stopped responding.
+    TimedOut,
+    /// Connection failed for other reason.
+    ConnectionLost(String),
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::ServerClosed => write!(f, "Server closed"),
+            DisconnectReason::Kicked(reason) => write!(f, "Kicked from the server: {reason}"),
+            DisconnectReason::TimedOut => write!(f, "Connection to the server timed out"),
+            DisconnectReason::ConnectionLost(reason) => write!(f, "Connection lost: {reason}"),
+        }
+    }
+}
+
+/// Tells the client why the server is about to disconnect it.
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
+struct DisconnectServerEvent {
+    reason: DisconnectReason,
+}
+
+/// Tells the server that the client leaves on its own.
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
+pub struct LeaveClientEvent;
+
+/// Ends the current session on this machine, whether connected or hosting.
+#[derive(Debug, Clone, Event)]
+pub struct LeaveSessionEvent {
+    /// Shown in the Multiplayer window, `None` when player left on its own.
+    pub reason: Option<String>,
+}
+
+/// Clients disconnected by the server after they are told the reason.
+#[derive(Resource, Default)]
+pub struct Disconnections {
+    queued: Vec<(ClientId, Removal)>,
+    pending: HashMap<ClientId, f32>,
+    shutdown: Shutdown,
+}
+
+enum Removal {
+    /// Accepted client, told the reason by replicon event.
+    Kick(DisconnectReason),
+    /// Client which was not accepted, told the reason on the control channel.
+    Reject(RejectReason),
+}
+
+#[derive(Default, PartialEq)]
+enum Shutdown {
+    #[default]
+    None,
+    Requested,
+    Pending(f32),
+}
+
+impl Disconnections {
+    pub fn kick(&mut self, client_id: ClientId, reason: impl Into<String>) {
+        let reason = DisconnectReason::Kicked(reason.into());
+        self.queued.push((client_id, Removal::Kick(reason)));
+    }
+
+    /// Refuses client which completed handshake, but is not allowed to play.
+    pub fn reject(&mut self, client_id: ClientId, reason: RejectReason) {
+        self.queued.push((client_id, Removal::Reject(reason)));
+    }
+
+    /// Tells all clients that the server is closing and stops hosting shortly after.
+    pub fn shutdown(&mut self) {
+        if self.shutdown == Shutdown::None {
+            self.shutdown = Shutdown::Requested;
+        }
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown != Shutdown::None
+    }
+}
+
+fn process_disconnections(
+    mut disconnections: ResMut<Disconnections>,
+    mut server: ResMut<RenetServer>,
+    mut disconnect_event: EventWriter<ToClients<DisconnectServerEvent>>,
+    mut leave_event: EventWriter<LeaveSessionEvent>,
+    time: Res<Time>,
+) {
+    let now = time.elapsed_seconds();
+
+    for (client_id, removal) in std::mem::take(&mut disconnections.queued) {
+        if disconnections.pending.contains_key(&client_id) {
+            continue;
+        }
+        match removal {
+            Removal::Kick(reason) => {
+                info!("Disconnecting player {client_id}: {reason}");
+                disconnect_event.send(ToClients {
+                    mode: SendMode::Direct(client_id.into()),
+                    event: DisconnectServerEvent { reason },
+                });
+            }
+            Removal::Reject(reason) => {
+                info!("Rejecting player {client_id}: {reason}");
+                protocol::send_rejection(&mut server, client_id.into(), reason);
+            }
+        }
+        disconnections
+            .pending
+            .insert(client_id, now + DISCONNECT_DELAY);
+    }
+
+    disconnections.pending.retain(|client_id, deadline| {
+        if *deadline > now {
+            return true;
+        }
+        server.disconnect((*client_id).into());
+        false
+    });
+
+    match disconnections.shutdown {
+        Shutdown::None => {}
+        Shutdown::Requested => {
+            info!("Closing the server.");
+            disconnect_event.send(ToClients {
+                mode: SendMode::Broadcast,
+                event: DisconnectServerEvent {
+                    reason: DisconnectReason::ServerClosed,
+                },
+            });
+            disconnections.shutdown = Shutdown::Pending(now + DISCONNECT_DELAY);
+        }
+        Shutdown::Pending(deadline) => {
+            if deadline <= now {
+                leave_event.send(LeaveSessionEvent { reason: None });
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn detect_client_disconnection(
+    mut commands: Commands,
+    mut disconnect_event: EventReader<DisconnectServerEvent>,
+    mut leave_event: EventWriter<LeaveSessionEvent>,
+    client: Res<RenetClient>,
+    transport: Res<NetcodeClientTransport>,
+    session: Res<ClientSession>,
+    settings: Res<ReconnectSettings>,
+    reconnecting: Option<Res<Reconnecting>>,
+    replicated: Query<Entity, With<Replication>>,
+    time: Res<Time>,
+) {
+    if let Some(DisconnectServerEvent { reason }) = disconnect_event.read().last() {
+        leave_event.send(LeaveSessionEvent {
+            reason: Some(reason.to_string()),
+        });
+        return;
+    }
+
+    if !client.is_disconnected() {
+        return;
+    }
+
+    let reason = match transport.disconnect_reason() {
+        Some(
+            NetcodeDisconnectReason::ConnectionTimedOut
+            | NetcodeDisconnectReason::ConnectionRequestTimedOut
+            | NetcodeDisconnectReason::ConnectionResponseTimedOut,
+        ) => DisconnectReason::TimedOut,
+        Some(NetcodeDisconnectReason::DisconnectedByServer) => DisconnectReason::ServerClosed,
+        Some(reason) => DisconnectReason::ConnectionLost(reason.to_string()),
+        None => match client.disconnect_reason() {
+            Some(reason) => DisconnectReason::ConnectionLost(reason.to_string()),
+            None => DisconnectReason::ConnectionLost(String::from("unknown reason")),
+        },
+    };
+
+    // Connection lost in the middle of the game might come back, the server does not close it.
+    let transient = matches!(
+        reason,
+        DisconnectReason::TimedOut | DisconnectReason::ConnectionLost(_)
+    );
+    if reconnecting.is_some() || (transient && session.established && settings.window > 0.0) {
+        if reconnecting.is_none() {
+            warn!("{reason}, reconnecting.");
+            commands.insert_resource(Reconnecting::new(
+                time.elapsed_seconds(),
+                &settings,
+                reason.to_string(),
+            ));
+        }
+        close_client(&mut commands, &replicated);
+        return;
+    }
+
+    leave_event.send(LeaveSessionEvent {
+        reason: Some(reason.to_string()),
+    });
+}
+
+/// Tells the server that the player is leaving on its own, so it does not wait for reconnection.
+fn notify_leave(
+    mut leave_event: EventReader<LeaveSessionEvent>,
+    mut leave_client_event: EventWriter<LeaveClientEvent>,
+    client: Res<RenetClient>,
+) {
+    if leave_event.read().any(|x| x.reason.is_none()) && client.is_connected() {
+        leave_client_event.send(LeaveClientEvent);
+    }
+}
+
+/// Removes everything which belongs to the connection of this client to the server.
+fn close_client(commands: &mut Commands, replicated: &Query<Entity, With<Replication>>) {
+    for entity in replicated {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    commands.remove_resource::<Client>();
+    commands.remove_resource::<ClientHandshake>();
+    commands.remove_resource::<RenetClient>();
+    commands.remove_resource::<NetcodeClientTransport>();
+    commands.remove_resource::<LocalPlayerResource>();
+    commands.remove_resource::<ConditionerRelay>();
+    commands.insert_resource(InterpolationClock::default());
+}
+
+/// Closes transports and removes everything which belongs to the session, so the game returns to
+/// the connect screen.
+fn leave_session(
+    mut commands: Commands,
+    mut leave_event: EventReader<LeaveSessionEvent>,
+    client_transport: Option<ResMut<NetcodeClientTransport>>,
+    server: Option<ResMut<RenetServer>>,
+    server_transport: Option<ResMut<NetcodeServerTransport>>,
+    state: Option<ResMut<MultiplayerUiState>>,
+    replicated: Query<Entity, With<Replication>>,
+) {
+    // First reason is the most specific one, later are only consequences of it.
+    let Some(reason) = leave_event.read().next().map(|x| x.reason.clone()) else {
+        return;
+    };
+    leave_event.clear();
+
+    match &reason {
+        Some(reason) => info!("Session ended: {reason}"),
+        None => info!("Session ended."),
+    }
+
+    if let Some(mut transport) = client_transport {
+        transport.disconnect();
+    }
+    if let (Some(mut server), Some(mut transport)) = (server, server_transport) {
+        transport.disconnect_all(&mut server);
+    }
+
+    close_client(&mut commands, &replicated);
+    commands.remove_resource::<ClientSession>();
+    commands.remove_resource::<Reconnecting>();
+    commands.remove_resource::<Server>();
+    commands.remove_resource::<RenetServer>();
+    commands.remove_resource::<NetcodeServerTransport>();
+    commands.insert_resource(Disconnections::default());
+    commands.insert_resource(PlayerSessions::default());
+
+    if let Some(mut state) = state {
+        state.last_error = reason;
+    }
+}
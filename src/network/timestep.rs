@@ -0,0 +1,79 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_replicon::{
+    client::ClientSet,
+    network_event::{
+        server_event::{SendMode, ServerEventAppExt, ToClients},
+        EventType,
+    },
+    server::ServerSet,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{client::ClientSession, has_server, protocol::ClientAcceptedEvent};
+
+/// Keeps fixed timestep of clients same as the one of the server, so inputs simulated on both
+/// sides move characters by the same distance.
+pub struct TimestepPlugin;
+
+impl Plugin for TimestepPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_server_event::<TimestepServerEvent>(EventType::Ordered)
+            .add_systems(
+                PreUpdate,
+                (
+                    apply_timestep.after(ClientSet::Receive),
+                    reset_timestep.run_if(resource_removed::<ClientSession>()),
+                ),
+            )
+            .add_systems(
+                PostUpdate,
+                send_timestep.before(ServerSet::Send).run_if(has_server),
+            );
+    }
+}
+
+/// Fixed timestep of the server, sent to new clients and to all clients when it changes.
+#[derive(Debug, Clone, Deserialize, Event, Serialize)]
+struct TimestepServerEvent {
+    timestep: Duration,
+}
+
+fn send_timestep(
+    mut accepted_event: EventReader<ClientAcceptedEvent>,
+    mut timestep_event: EventWriter<ToClients<TimestepServerEvent>>,
+    mut last_sent: Local<Option<Duration>>,
+    fixed_time: Res<Time<Fixed>>,
+) {
+    let timestep = fixed_time.timestep();
+    if *last_sent != Some(timestep) {
+        *last_sent = Some(timestep);
+        timestep_event.send(ToClients {
+            mode: SendMode::Broadcast,
+            event: TimestepServerEvent { timestep },
+        });
+    }
+
+    for event in accepted_event.read() {
+        timestep_event.send(ToClients {
+            mode: SendMode::Direct(event.client_id.into()),
+            event: TimestepServerEvent { timestep },
+        });
+    }
+}
+
+fn apply_timestep(
+    mut timestep_event: EventReader<TimestepServerEvent>,
+    mut fixed_time: ResMut<Time<Fixed>>,
+) {
+    if let Some(event) = timestep_event.read().last() {
+        info!("Fixed timestep of the server is {:?}", event.timestep);
+        fixed_time.set_timestep(event.timestep);
+    }
+}
+
+/// Goes back to the default timestep after leaving the server.
+fn reset_timestep(mut fixed_time: ResMut<Time<Fixed>>) {
+    fixed_time.set_timestep(Duration::from_secs_f64(crate::TIMESTEP));
+}
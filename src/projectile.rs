@@ -0,0 +1,518 @@
+pub mod history;
+
+use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy_egui::EguiPlugin;
+use bevy_rapier3d::prelude::*;
+use bevy_replicon::{
+    prelude::*,
+    replicon_core::{
+        dont_replicate::CommandDontReplicateExt,
+        replication_rules::{AppReplicationExt, Replication},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{
+        health::{DamageEvent, DamageSource, Downed, Health},
+        player::{self, Player},
+        MoveCharacters,
+    },
+    network::{
+        client::ClientId,
+        has_client, has_server,
+        protocol::ProtocolAppExt,
+        replication::transform::{InterpolationClock, InterpolationSettings},
+    },
+};
+
+use self::history::{HistoryPlugin, TransformHistory};
+
+/// Highest number of ticks by which targets are rewound, so players with high ping can not hit
+/// targets which already moved away long ago.
+const MAX_REWIND_TICKS: f64 = 15.0;
+/// Seconds after which ballistic projectiles which hit nothing are removed.
+const MAX_FLIGHT_TIME: f32 = 5.0;
+/// Seconds for which tracers of hitscan projectiles are shown.
+const TRACER_TIME: f32 = 0.3;
+/// Drag below which projectiles fly in a parabola, avoids dividing by zero.
+const MIN_DRAG: f32 = 0.0001;
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(HistoryPlugin)
+            .replicate::<Projectile>()
+            .add_protocol_type::<Projectile>()
+            .add_systems(PostUpdate, init_projectiles)
+            .add_systems(
+                FixedUpdate,
+                simulate_projectiles
+                    .after(MoveCharacters)
+                    .run_if(has_server),
+            );
+
+        // Clients simulate flight from replicated spawn parameters only to show it.
+        if app.is_plugin_added::<EguiPlugin>() {
+            app.add_systems(
+                Update,
+                (follow_projectiles.run_if(has_client), draw_tracers),
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ProjectileKind {
+    /// Flies under gravity, slowed by drag proportional to its velocity.
+    Ballistic { gravity: f32, drag: f32 },
+    /// Hits instantly along a straight line, the length is shortened to the first hit.
+    Hitscan { length: f32 },
+}
+
+/// Projectile replicated only by its spawn parameters, every machine computes its flight.
+#[derive(Debug, Clone, Component, Serialize, Deserialize)]
+pub struct Projectile {
+    pub shooter: ClientId,
+    pub kind: ProjectileKind,
+    pub origin: Vec3,
+    /// Initial velocity, or direction of hitscan projectiles.
+    pub velocity: Vec3,
+    pub radius: f32,
+    pub damage: f32,
+    /// Tick of the server at which the projectile was fired.
+    pub fired_at: RepliconTick,
+}
+
+impl Projectile {
+    /// Position after the seconds of flight, has closed form so machines agree on it.
+    pub fn position(&self, time: f32) -> Vec3 {
+        match self.kind {
+            ProjectileKind::Ballistic { gravity, drag } => {
+                let gravity = Vec3::NEG_Y * gravity;
+                if drag < MIN_DRAG {
+                    return self.origin + self.velocity * time + gravity * time * time / 2.0;
+                }
+                // Terms cancel out at low drag, double precision keeps it close to the parabola.
+                let (drag, time) = (drag as f64, time as f64);
+                let travel = -(-drag * time).exp_m1() / drag;
+                let fall = (time - travel) / drag;
+                self.origin + self.velocity * travel as f32 + gravity * fall as f32
+            }
+            ProjectileKind::Hitscan { length } => {
+                self.origin + self.velocity.normalize_or_zero() * length
+            }
+        }
+    }
+
+    pub fn velocity(&self, time: f32) -> Vec3 {
+        match self.kind {
+            ProjectileKind::Ballistic { gravity, drag } => {
+                let gravity = Vec3::NEG_Y * gravity;
+                if drag < MIN_DRAG {
+                    return self.velocity + gravity * time;
+                }
+                let decay = (-drag * time).exp_m1();
+                self.velocity * (1.0 + decay) - gravity * decay / drag
+            }
+            ProjectileKind::Hitscan { .. } => self.velocity,
+        }
+    }
+}
+
+/// State of projectile simulated by the server.
+#[derive(Debug, Clone, Component)]
+struct ProjectileFlight {
+    /// Seconds since the projectile was fired.
+    elapsed: f32,
+    /// Ticks by which targets are rewound, so they are where the shooter saw them.
+    rewind: f64,
+    /// Whether hitscan projectile already hit, it stays only to show its tracer.
+    resolved: bool,
+}
+
+/// Spawns projectile on the server, `view_tick` is the tick at which the shooter saw other
+/// characters when firing.
+pub fn spawn<'w, 's, 'a>(
+    commands: &'a mut Commands<'w, 's>,
+    projectile: Projectile,
+    view_tick: Option<f64>,
+) -> EntityCommands<'w, 's, 'a> {
+    let rewind = rewind_ticks(projectile.fired_at.get() as f64, view_tick);
+    let transform = Transform::from_translation(projectile.origin);
+    let mut entity_commands = commands.spawn((
+        projectile,
+        ProjectileFlight {
+            elapsed: 0.0,
+            rewind,
+            resolved: false,
+        },
+        TransformBundle::from_transform(transform),
+        Replication,
+    ));
+    entity_commands.dont_replicate::<Transform>();
+    entity_commands
+}
+
+/// Ticks by which targets are rewound for projectile fired at the tick, limited so players can
+/// not shoot too far into the past, or into the future.
+fn rewind_ticks(fired_at: f64, view_tick: Option<f64>) -> f64 {
+    view_tick.map_or(0.0, |x| (fired_at - x).clamp(0.0, MAX_REWIND_TICKS))
+}
+
+/// Gives transform to projectiles received from the server.
+fn init_projectiles(
+    mut commands: Commands,
+    projectiles: Query<(Entity, &Projectile), (Added<Projectile>, Without<Transform>)>,
+) {
+    for (entity, projectile) in &projectiles {
+        commands
+            .entity(entity)
+            .insert(TransformBundle::from_transform(
+                Transform::from_translation(projectile.origin),
+            ));
+    }
+}
+
+/// Moves projectiles and damages characters which they hit where the shooter saw them.
+fn simulate_projectiles(
+    mut commands: Commands,
+    mut damage_event: EventWriter<DamageEvent>,
+    mut projectiles: Query<(
+        Entity,
+        &mut Projectile,
+        &mut ProjectileFlight,
+        &mut Transform,
+    )>,
+    targets: Query<(Entity, &TransformHistory, Option<&Player>), (With<Health>, Without<Downed>)>,
+    context: Res<RapierContext>,
+    tick: Res<RepliconTick>,
+    time: Res<Time>,
+) {
+    for (entity, mut projectile, mut flight, mut transform) in &mut projectiles {
+        let from = projectile.position(flight.elapsed);
+        flight.elapsed += time.delta_seconds();
+        let view_tick = tick.get() as f64 - flight.rewind;
+
+        if let ProjectileKind::Hitscan { length } = projectile.kind {
+            if flight.elapsed > TRACER_TIME {
+                commands.entity(entity).despawn_recursive();
+            } else if !flight.resolved {
+                flight.resolved = true;
+                let origin = projectile.origin;
+                let hit = find_hit(&projectile, origin, from, view_tick, &context, &targets);
+                let distance = hit.map_or(length, |(_, x)| origin.distance(x));
+                projectile.kind = ProjectileKind::Hitscan { length: distance };
+                if let Some((Some(target), _)) = hit {
+                    damage(&mut damage_event, &projectile, target);
+                }
+            }
+            continue;
+        }
+
+        let to = projectile.position(flight.elapsed);
+        transform.translation = to;
+        let velocity = projectile.velocity(flight.elapsed).normalize_or_zero();
+        if velocity != Vec3::ZERO {
+            transform.rotation = Quat::from_rotation_arc(Vec3::Y, velocity);
+        }
+        match find_hit(&projectile, from, to, view_tick, &context, &targets) {
+            Some((target, _)) => {
+                if let Some(target) = target {
+                    damage(&mut damage_event, &projectile, target);
+                }
+                commands.entity(entity).despawn_recursive();
+            }
+            None if flight.elapsed > MAX_FLIGHT_TIME => {
+                commands.entity(entity).despawn_recursive();
+            }
+            None => (),
+        }
+    }
+}
+
+fn damage(damage_event: &mut EventWriter<DamageEvent>, projectile: &Projectile, target: Entity) {
+    damage_event.send(DamageEvent {
+        target,
+        amount: projectile.damage,
+        source: DamageSource::Client(projectile.shooter),
+    });
+}
+
+/// Finds the first hit on the segment, returns the hit character, `None` for the level, and
+/// the point of the hit. Characters are rewound to the view tick and tested as capsules.
+fn find_hit(
+    projectile: &Projectile,
+    from: Vec3,
+    to: Vec3,
+    view_tick: f64,
+    context: &RapierContext,
+    targets: &Query<(Entity, &TransformHistory, Option<&Player>), (With<Health>, Without<Downed>)>,
+) -> Option<(Option<Entity>, Vec3)> {
+    let length = from.distance(to);
+    let direction = (to - from).normalize_or_zero();
+    let mut closest = context
+        .cast_ray(from, direction, length, true, QueryFilter::only_fixed())
+        .map(|(_, toi)| (None, toi));
+
+    for (target, history, player) in targets {
+        if player.map_or(false, |x| x.client_id == projectile.shooter) {
+            continue;
+        }
+        let Some(center) = history.sample(view_tick) else {
+            continue;
+        };
+
+        let bottom = center - Vec3::Y * player::HALF_HEIGHT;
+        let top = center + Vec3::Y * player::HALF_HEIGHT;
+        let (distance, toi) = segment_distance(from, to, bottom, top);
+        if distance <= player::RADIUS + projectile.radius
+            && closest.map_or(true, |(_, x)| toi * length < x)
+        {
+            closest = Some((Some(target), toi * length));
+        }
+    }
+
+    closest.map(|(target, toi)| (target, from + direction * toi))
+}
+
+/// Returns distance between two segments and the fraction of the first segment at which they
+/// are the closest.
+fn segment_distance(p1: Vec3, q1: Vec3, p2: Vec3, q2: Vec3) -> (f32, f32) {
+    let d1 = q1 - p1;
+    let d2 = q2 - p2;
+    let r = p1 - p2;
+    let a = d1.length_squared();
+    let e = d2.length_squared();
+    let f = d2.dot(r);
+
+    let (s, t) = if a <= f32::EPSILON {
+        (0.0, (f / e).clamp(0.0, 1.0))
+    } else {
+        let c = d1.dot(r);
+        let b = d1.dot(d2);
+        let denominator = a * e - b * b;
+        let s = match denominator > f32::EPSILON {
+            true => ((b * f - c * e) / denominator).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        let t = (b * s + f) / e;
+        match t {
+            t if t < 0.0 => ((-c / a).clamp(0.0, 1.0), 0.0),
+            t if t > 1.0 => (((b - c) / a).clamp(0.0, 1.0), 1.0),
+            t => (s, t),
+        }
+    };
+
+    ((p1 + d1 * s).distance(p2 + d2 * t), s)
+}
+
+/// Moves projectiles received from the server along their flight, at the tick in which other
+/// remote entities are rendered.
+fn follow_projectiles(
+    mut projectiles: Query<
+        (&Projectile, &mut Transform, &mut Visibility),
+        Without<ProjectileFlight>,
+    >,
+    clock: Res<InterpolationClock>,
+    settings: Res<InterpolationSettings>,
+) {
+    let Some(render_tick) = clock.render_tick(&settings) else {
+        return;
+    };
+
+    for (projectile, mut transform, mut visibility) in &mut projectiles {
+        let time = (render_tick - projectile.fired_at.get() as f64) / clock.tick_rate();
+        *visibility = match time >= 0.0 {
+            true => Visibility::Inherited,
+            false => Visibility::Hidden,
+        };
+        let time = time.max(0.0) as f32;
+        transform.translation = projectile.position(time);
+        if let ProjectileKind::Ballistic { .. } = projectile.kind {
+            let velocity = projectile.velocity(time).normalize_or_zero();
+            if velocity != Vec3::ZERO {
+                transform.rotation = Quat::from_rotation_arc(Vec3::Y, velocity);
+            }
+        }
+    }
+}
+
+fn draw_tracers(mut gizmos: Gizmos, projectiles: Query<&Projectile>) {
+    for projectile in &projectiles {
+        if let ProjectileKind::Hitscan { .. } = projectile.kind {
+            gizmos.line(
+                projectile.origin,
+                projectile.position(0.0),
+                Color::rgb(0.7, 0.9, 1.0),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 0.0001;
+
+    fn ballistic(gravity: f32, drag: f32) -> Projectile {
+        Projectile {
+            shooter: ClientId::from_raw(1),
+            kind: ProjectileKind::Ballistic { gravity, drag },
+            origin: Vec3::new(1.0, 2.0, 3.0),
+            velocity: Vec3::new(10.0, 5.0, -4.0),
+            radius: 0.1,
+            damage: 10.0,
+            fired_at: RepliconTick::default(),
+        }
+    }
+
+    /// Integrates the flight in small steps, which the closed form should agree with.
+    fn integrate(projectile: &Projectile, time: f32) -> Vec3 {
+        let ProjectileKind::Ballistic { gravity, drag } = projectile.kind else {
+            unreachable!();
+        };
+        let steps = 100_000;
+        let dt = time / steps as f32;
+        let mut position = projectile.origin;
+        let mut velocity = projectile.velocity;
+        for _ in 0..steps {
+            let acceleration = Vec3::NEG_Y * gravity - velocity * drag;
+            position += (velocity + acceleration * dt / 2.0) * dt;
+            velocity += acceleration * dt;
+        }
+        position
+    }
+
+    #[test]
+    fn ballistic_without_drag_is_parabola() {
+        let projectile = ballistic(9.81, 0.0);
+        for time in [0.0, 0.5, 1.0, 2.5] {
+            let expected =
+                projectile.origin + projectile.velocity * time - Vec3::Y * 9.81 * time * time / 2.0;
+            assert!(projectile.position(time).abs_diff_eq(expected, EPSILON));
+        }
+    }
+
+    #[test]
+    fn ballistic_with_drag_matches_integration() {
+        for drag in [0.05, 0.5, 2.0] {
+            let projectile = ballistic(9.81, drag);
+            assert_eq!(projectile.position(0.0), projectile.origin);
+            for time in [0.5, 1.0, 2.5] {
+                let expected = integrate(&projectile, time);
+                let position = projectile.position(time);
+                assert!(
+                    position.abs_diff_eq(expected, 0.01),
+                    "drag {drag}, time {time}: {position} != {expected}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_drag_is_continuous() {
+        let time = 2.0;
+        let parabola = ballistic(9.81, 0.0).position(time);
+        let below = ballistic(9.81, MIN_DRAG / 2.0).position(time);
+        let above = ballistic(9.81, MIN_DRAG * 2.0).position(time);
+        assert!(below.abs_diff_eq(parabola, EPSILON));
+        assert!(above.abs_diff_eq(parabola, 0.01));
+
+        let parabola = ballistic(9.81, 0.0).velocity(time);
+        let above = ballistic(9.81, MIN_DRAG * 2.0).velocity(time);
+        assert!(above.abs_diff_eq(parabola, 0.01));
+    }
+
+    #[test]
+    fn segment_distance_crossing() {
+        let (distance, s) = segment_distance(
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.5, -1.0, 2.0),
+            Vec3::new(0.5, 1.0, 2.0),
+        );
+        assert!((distance - 2.0).abs() < EPSILON);
+        assert!((s - 0.75).abs() < EPSILON);
+    }
+
+    #[test]
+    fn segment_distance_endpoints() {
+        // Closest points are past the end of the first segment and the start of the second.
+        let (distance, s) = segment_distance(
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::new(3.0, 0.0, 0.0),
+            Vec3::new(3.0, 4.0, 0.0),
+        );
+        assert!((distance - 2.0).abs() < EPSILON);
+        assert!((s - 1.0).abs() < EPSILON);
+
+        // Closest point is before the start of the first segment.
+        let (distance, s) = segment_distance(
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::new(-2.0, -1.0, 0.0),
+            Vec3::new(-2.0, 1.0, 0.0),
+        );
+        assert!((distance - 2.0).abs() < EPSILON);
+        assert_eq!(s, 0.0);
+    }
+
+    #[test]
+    fn segment_distance_degenerate() {
+        // First segment is a point, as is the hitscan projectile which did not move.
+        let (distance, s) = segment_distance(
+            Vec3::new(0.0, 5.0, 1.0),
+            Vec3::new(0.0, 5.0, 1.0),
+            Vec3::ZERO,
+            Vec3::Y,
+        );
+        assert!((distance - 4.0_f32.hypot(1.0)).abs() < EPSILON);
+        assert_eq!(s, 0.0);
+    }
+
+    #[test]
+    fn segment_distance_parallel() {
+        // Overlapping parallel segments are apart by their offset.
+        let (distance, s) = segment_distance(
+            Vec3::ZERO,
+            Vec3::new(0.0, 2.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 3.0, 0.0),
+        );
+        assert!((distance - 1.0).abs() < EPSILON);
+        assert!((0.0..=1.0).contains(&s));
+
+        // Parallel segments one after another are apart between their nearest ends.
+        let (distance, s) = segment_distance(
+            Vec3::ZERO,
+            Vec3::Y,
+            Vec3::new(0.0, 4.0, 0.0),
+            Vec3::new(0.0, 5.0, 0.0),
+        );
+        assert!((distance - 3.0).abs() < EPSILON);
+        assert_eq!(s, 1.0);
+
+        let (distance, s) = segment_distance(
+            Vec3::new(0.0, 4.0, 0.0),
+            Vec3::new(0.0, 5.0, 0.0),
+            Vec3::ZERO,
+            Vec3::Y,
+        );
+        assert!((distance - 3.0).abs() < EPSILON);
+        assert_eq!(s, 0.0);
+    }
+
+    #[test]
+    fn rewind_is_clamped() {
+        assert_eq!(rewind_ticks(100.0, None), 0.0);
+        assert_eq!(rewind_ticks(100.0, Some(95.5)), 4.5);
+        assert_eq!(rewind_ticks(100.0, Some(100.0)), 0.0);
+        // Clients can not claim to see the future, nor too far into the past.
+        assert_eq!(rewind_ticks(100.0, Some(110.0)), 0.0);
+        assert_eq!(rewind_ticks(100.0, Some(0.0)), MAX_REWIND_TICKS);
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use bevy_replicon::{prelude::*, server::ServerSet};
+
+use crate::{character::CharacterVectors, network::has_server};
+
+/// Number of ticks for which positions of characters are kept.
+const HISTORY_LENGTH: usize = 32;
+
+pub struct HistoryPlugin;
+
+impl Plugin for HistoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (init_history, record_history)
+                .chain()
+                .after(PhysicsSet::Writeback)
+                .after(ServerSet::Send)
+                .run_if(has_server),
+        );
+    }
+}
+
+/// Positions of a character in the last ticks sent to clients, only on the server.
+#[derive(Debug, Clone, Default, Component)]
+pub struct TransformHistory {
+    snapshots: VecDeque<(RepliconTick, Vec3)>,
+}
+
+impl TransformHistory {
+    /// Returns position at the tick, possibly fractional, interpolated between recorded ticks
+    /// and clamped to the oldest and newest of them.
+    pub fn sample(&self, tick: f64) -> Option<Vec3> {
+        let index = self
+            .snapshots
+            .partition_point(|(x, _)| x.get() as f64 <= tick);
+        match (index.checked_sub(1), self.snapshots.get(index)) {
+            (Some(previous), Some((to_tick, to))) => {
+                let (from_tick, from) = self.snapshots[previous];
+                let from_tick = from_tick.get() as f64;
+                let t = (tick - from_tick) / (to_tick.get() as f64 - from_tick);
+                Some(from.lerp(*to, t as f32))
+            }
+            (Some(previous), None) => Some(self.snapshots[previous].1),
+            (None, _) => self.snapshots.front().map(|(_, x)| *x),
+        }
+    }
+
+    fn record(&mut self, tick: RepliconTick, position: Vec3) {
+        match self.snapshots.back_mut() {
+            Some((x, snapshot)) if *x == tick => *snapshot = position,
+            _ => self.snapshots.push_back((tick, position)),
+        }
+
+        if self.snapshots.len() > HISTORY_LENGTH {
+            self.snapshots.pop_front();
+        }
+    }
+}
+
+fn init_history(
+    mut commands: Commands,
+    characters: Query<Entity, (With<CharacterVectors>, Without<TransformHistory>)>,
+) {
+    for entity in &characters {
+        commands.entity(entity).insert(TransformHistory::default());
+    }
+}
+
+/// Records positions of characters as they were sent with the current tick.
+fn record_history(mut query: Query<(&Transform, &mut TransformHistory)>, tick: Res<RepliconTick>) {
+    for (transform, mut history) in &mut query {
+        history.record(*tick, transform.translation);
+    }
+}